@@ -0,0 +1,451 @@
+use crate::audit::{self, AuditAction};
+use crate::db::{Pagination, DATABASE};
+use crate::i18n::t;
+use crate::output::{Account, OpenSession};
+use crate::search::SortOrder;
+use crate::service::{self, ServiceError, ServiceResult};
+use crate::session::Session;
+use crate::token::{self, Scope};
+use crate::utils::input_validation::normalize_name;
+use crate::{Review, Scores, User};
+use clap::ValueEnum;
+use percent_encoding::percent_decode_str;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::Read;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+pub static DEFAULT_ADDR: &str = "127.0.0.1:8080";
+const MAX_BODY_SIZE: u64 = 64 * 1024;
+
+/// Status and JSON body of a response
+type Reply = (u16, Value);
+
+#[derive(Deserialize)]
+struct Credentials {
+    name: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+struct NewReview {
+    comment: String,
+    grade: Option<u8>,
+    #[serde(default)]
+    scores: Scores,
+}
+
+#[derive(Deserialize)]
+struct NewVote {
+    helpful: bool,
+}
+
+/// Listen on `addr` and answer requests until the process is stopped
+pub fn serve(addr: &str) -> anyhow::Result<()> {
+    let server = Server::http(addr).map_err(|e| anyhow::anyhow!(t!("error.listen", addr, e)))?;
+    println!("{}", t!("msg.api_listening", server.server_addr()));
+
+    for request in server.incoming_requests() {
+        respond(request);
+    }
+
+    Ok(())
+}
+
+fn respond(mut request: Request) {
+    let token = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+        .map(str::to_string);
+
+    let mut body = String::new();
+    let (status, value) = match request.as_reader().take(MAX_BODY_SIZE).read_to_string(&mut body) {
+        Ok(..) => handle(request.method(), request.url(), token.as_deref(), &body),
+        Err(..) => error(400, &t!("error.body_encoding")),
+    };
+
+    // The server only stops when killed, so changes are written right away,
+    // except in tests which must not touch the real database
+    if (200..300).contains(&status) && *request.method() != Method::Get && !cfg!(test) {
+        if let Err(e) = DATABASE.lock().unwrap().save() {
+            eprintln!("{}", t!("error.save_db", e));
+        }
+    }
+
+    let content_type = Header::from_bytes("Content-Type", "application/json; charset=utf-8").unwrap();
+    let response = Response::from_string(value.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+    if let Err(e) = request.respond(response) {
+        eprintln!("{}", t!("error.respond", e));
+    }
+}
+
+fn error(status: u16, message: &str) -> Reply {
+    (status, json!({ "error": message }))
+}
+
+fn service_error(e: ServiceError) -> Reply {
+    let status = match e {
+        ServiceError::Authentication => 401,
+        ServiceError::Denied(..) => 403,
+        ServiceError::NotFound(..) => 404,
+        ServiceError::Invalid(..) => 400,
+        ServiceError::Failed(..) => 500,
+    };
+    error(status, &e.to_string())
+}
+
+fn parse<T: DeserializeOwned>(body: &str) -> ServiceResult<T> {
+    serde_json::from_str(body).map_err(|e| ServiceError::Invalid(t!("error.invalid_body", e)))
+}
+
+/// Keep the items selected by the `offset` and `limit` parameters of the query string
+fn paginated<T: Clone>(url: &str, items: Vec<T>) -> ServiceResult<Vec<T>> {
+    let mut pagination = Pagination::default();
+    let query = url.split_once('?').map(|(_, query)| query).unwrap_or_default();
+    for (name, value) in query.split('&').filter_map(|param| param.split_once('=')) {
+        let number = || {
+            value
+                .parse()
+                .map_err(|_| ServiceError::Invalid(t!("error.invalid_param", name)))
+        };
+        match name {
+            "offset" => pagination.offset = number()?,
+            "limit" => pagination.limit = Some(number()?),
+            _ => {}
+        }
+    }
+    Ok(pagination.apply(&items).items)
+}
+
+/// Sort reviews according to the `sort` parameter of the query string, if any
+fn sorted(url: &str, mut reviews: Vec<Review>) -> ServiceResult<Vec<Review>> {
+    let query = url.split_once('?').map(|(_, query)| query).unwrap_or_default();
+    if let Some((_, value)) = query.split('&').filter_map(|param| param.split_once('=')).find(|(name, _)| *name == "sort") {
+        let sort = SortOrder::from_str(value, true).map_err(|_| ServiceError::Invalid(t!("error.invalid_param", "sort")))?;
+        service::sort_reviews(&mut reviews, sort);
+    }
+    Ok(reviews)
+}
+
+fn reply<T: serde::Serialize>(status: u16, result: ServiceResult<T>) -> Reply {
+    match result.and_then(|value| serde_json::to_value(value).map_err(|e| ServiceError::Failed(e.into()))) {
+        Ok(value) => (status, value),
+        Err(e) => service_error(e),
+    }
+}
+
+/// Route a request, every route but the login one requiring a session or access token
+fn handle(method: &Method, url: &str, token: Option<&str>, body: &str) -> Reply {
+    let path = url.split('?').next().unwrap_or_default();
+    let segments: Vec<String> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| normalize_name(&percent_decode_str(segment).decode_utf8_lossy()))
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    if let (Method::Post, ["login"]) = (method, &segments[..]) {
+        return reply(200, login(body));
+    }
+
+    let caller = match authenticate(token) {
+        Ok(caller) => caller,
+        Err(e) => return error(401, &e.to_string()),
+    };
+    let user = &caller.user;
+
+    match (method, &segments[..]) {
+        (Method::Post, ["logout"]) => {
+            if let Some(session) = &caller.session {
+                session.close();
+            }
+            (200, json!({}))
+        }
+        (Method::Get, ["users"]) => scoped(&caller, "manage", || reply(200, users(user))),
+        (Method::Get, ["sessions"]) => scoped(&caller, "manage", || reply(200, sessions(user))),
+        (Method::Delete, ["sessions", id]) => scoped(&caller, "manage", || {
+            reply(200, service::revoke_session(user, id).map(OpenSession::from))
+        }),
+        (Method::Get, ["reviews"]) => scoped(&caller, "read", || {
+            reply(200, paginated(url, service::own_reviews(user)))
+        }),
+        (Method::Get, ["establishments"]) => {
+            scoped(&caller, "read", || reply(200, Ok(service::establishments(user))))
+        }
+        (Method::Get, ["establishments", establishment, "reviews"]) => scoped(&caller, "read", || {
+            let reviews = sorted(url, service::establishment_reviews(user, establishment));
+            reply(200, reviews.and_then(|reviews| paginated(url, reviews)))
+        }),
+        (Method::Get, ["establishments", establishment, "stats"]) => {
+            scoped(&caller, "stats", || reply(200, service::establishment_stats(user, establishment)))
+        }
+        (Method::Post, ["establishments", establishment, "reviews"]) => scoped(&caller, "review", || {
+            reply(
+                201,
+                parse::<NewReview>(body).and_then(|review| {
+                    service::add_review(user, establishment, &review.comment, review.grade, review.scores)
+                }),
+            )
+        }),
+        (Method::Post, ["establishments", establishment, "reviews", reviewer, "votes"]) => scoped(&caller, "vote", || {
+            reply(
+                200,
+                parse::<NewVote>(body).and_then(|vote| service::vote(user, establishment, reviewer, vote.helpful)),
+            )
+        }),
+        (Method::Delete, ["establishments", establishment, "reviews", reviewer]) => scoped(&caller, "delete", || {
+            reply(200, service::delete_review(user, establishment, reviewer))
+        }),
+        _ => error(404, &t!("error.unknown_route")),
+    }
+}
+
+/// Client of the API, authenticated either by a session opened with `/login`
+/// or by a personal access token restricted to some actions
+struct Caller {
+    user: User,
+    session: Option<Session>,
+    scopes: Option<Vec<Scope>>,
+}
+
+fn authenticate(token: Option<&str>) -> anyhow::Result<Caller> {
+    let Some(token) = token else {
+        anyhow::bail!(t!("error.missing_token"))
+    };
+
+    if token::is_api_token(token) {
+        let (user, scopes) = token::authenticate(token)?;
+        return Ok(Caller {
+            user,
+            session: None,
+            scopes: Some(scopes),
+        });
+    }
+
+    let session = Session::find(token)?.ok_or_else(|| anyhow::anyhow!(t!("error.missing_token")))?;
+    Ok(Caller {
+        user: session.user()?,
+        session: Some(session),
+        scopes: None,
+    })
+}
+
+/// Run a route only if the caller's token grants the casbin action it performs,
+/// the action itself being then checked against the policy by the service layer
+fn scoped(caller: &Caller, act: &str, route: impl FnOnce() -> Reply) -> Reply {
+    match &caller.scopes {
+        Some(scopes) if !scopes.iter().any(|scope| scope.allows(act)) => {
+            audit::record(
+                &caller.user.name,
+                AuditAction::AccessDenied,
+                &format!("scope={}", act),
+            );
+            error(403, &t!("error.token_scope", act))
+        }
+        _ => route(),
+    }
+}
+
+fn login(body: &str) -> ServiceResult<Value> {
+    let credentials: Credentials = parse(body)?;
+    let user = service::authenticate(&credentials.name, &credentials.password)?;
+    let (_, secret) = Session::open(&user)?;
+    Ok(json!({ "token": secret }))
+}
+
+fn users(user: &User) -> ServiceResult<Vec<Account>> {
+    Ok(service::users(user)?.into_iter().map(Account::from).collect())
+}
+
+fn sessions(user: &User) -> ServiceResult<Vec<OpenSession>> {
+    Ok(service::sessions(user)?.into_iter().map(OpenSession::from).collect())
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::password::hash_password;
+    use crate::Role;
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::thread;
+    use strum::IntoEnumIterator;
+
+    static PASSWORD: &str = "Wn3!qTz8#pLk2vRs";
+
+    /// Store an administrator in the test database, and reviews of `establishment` by others
+    fn fixture(admin: &str, establishment: &str) -> User {
+        let user = User::new(admin, &hash_password(PASSWORD.as_bytes()), Role::Admin);
+        let mut db = DATABASE.lock().unwrap();
+        db.store_user(&user).unwrap();
+        for (reviewer, grade) in [("a", 1), ("b", 5)] {
+            db.store_review(&Review::new(establishment, &format!("{} {}", establishment, reviewer), "Correct", grade)).unwrap();
+        }
+        user
+    }
+
+    /// Start a server on a free local port answering `count` requests
+    fn start(count: usize) -> String {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap().to_string();
+        thread::spawn(move || {
+            for request in server.incoming_requests().take(count) {
+                respond(request);
+            }
+        });
+        addr
+    }
+
+    fn call(addr: &str, method: &str, path: &str, token: Option<&str>, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let authorization = token.map_or(String::new(), |token| format!("Authorization: Bearer {}\r\n", token));
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            path,
+            addr,
+            authorization,
+            body.len(),
+            body
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn test_api_over_localhost() {
+        let addr = start(14);
+
+        let (status, _) = call(&addr, "GET", "/reviews", None, "");
+        assert_eq!(status, 401);
+
+        fixture("api admin", "API Burger");
+        let login = |password: &str| format!(r#"{{"name": "api admin", "password": "{}"}}"#, password);
+
+        let (status, _) = call(&addr, "POST", "/login", None, &login("wrong"));
+        assert_eq!(status, 401);
+
+        let (status, body) = call(&addr, "POST", "/login", None, &login(PASSWORD));
+        assert_eq!(status, 200);
+        let token = body["token"].as_str().unwrap().to_string();
+        let token = Some(token.as_str());
+
+        let (status, body) = call(&addr, "GET", "/establishments/API%20Burger/reviews", token, "");
+        assert_eq!(status, 200);
+        assert_eq!(body.as_array().unwrap().len(), 2);
+
+        let (status, body) = call(&addr, "GET", "/establishments/API%20Burger/reviews?offset=1&limit=1", token, "");
+        assert_eq!(status, 200);
+        assert_eq!(body.as_array().unwrap().len(), 1);
+
+        let (status, _) = call(&addr, "GET", "/establishments/API%20Burger/reviews?limit=all", token, "");
+        assert_eq!(status, 400);
+
+        let (status, body) = call(&addr, "GET", "/establishments/API%20Burger/stats", token, "");
+        assert_eq!(status, 200);
+        assert_eq!(body["distribution"].as_array().unwrap().len(), 5);
+
+        let (status, _) = call(&addr, "POST", "/establishments/API%20Bistrot/reviews", token, r#"{"comment": "Bon", "grade": 9}"#);
+        assert_eq!(status, 400);
+
+        let (status, body) = call(&addr, "POST", "/establishments/API%20Bistrot/reviews", token, r#"{"comment": "Bon", "grade": 4}"#);
+        assert_eq!(status, 201);
+        assert_eq!(body["establishment"], "API Bistrot");
+
+        let (status, _) = call(&addr, "POST", "/establishments/API%20Bistrot/reviews/api%20admin/votes", token, r#"{"helpful": true}"#);
+        assert_eq!(status, 403);
+
+        let (status, _) = call(&addr, "GET", "/establishments/API%20Bistrot/reviews?sort=helpful", token, "");
+        assert_eq!(status, 200);
+
+        let (status, _) = call(&addr, "GET", "/establishments/API%20Bistrot/reviews?sort=random", token, "");
+        assert_eq!(status, 400);
+
+        let (status, _) = call(&addr, "DELETE", "/establishments/API%20Bistrot/reviews/api%20admin", token, "");
+        assert_eq!(status, 200);
+
+        let (status, _) = call(&addr, "GET", "/unknown", token, "");
+        assert_eq!(status, 404);
+    }
+
+    #[test]
+    fn test_api_token_scopes() {
+        let addr = start(4);
+        let user = fixture("scoped admin", "Scoped Burger");
+        let read_only = token::create(&user, "lecture", vec![Scope::Read], 1).unwrap();
+        let token = Some(read_only.as_str());
+
+        let (status, _) = call(&addr, "GET", "/establishments/Scoped%20Burger/reviews", token, "");
+        assert_eq!(status, 200);
+
+        let (status, _) = call(&addr, "POST", "/establishments/Scoped%20Bistrot/reviews", token, r#"{"comment": "Bon", "grade": 4}"#);
+        assert_eq!(status, 403);
+
+        let (status, _) = call(&addr, "GET", "/users", token, "");
+        assert_eq!(status, 403);
+
+        let (status, _) = call(&addr, "GET", "/reviews", Some("slh_00000000_forged"), "");
+        assert_eq!(status, 401);
+    }
+
+    #[test]
+    fn test_revoked_session_is_refused() {
+        let addr = start(5);
+        let user = fixture("session admin", "Session Burger");
+        let (_, admin) = Session::open(&user).unwrap();
+        let (other, revoked) = Session::open(&user).unwrap();
+
+        let (status, body) = call(&addr, "GET", "/sessions", Some(&admin), "");
+        assert_eq!(status, 200);
+        assert!(body.as_array().unwrap().iter().any(|session| session["id"] == other.id.as_str()));
+        assert!(body.as_array().unwrap().iter().all(|session| session.get("hash").is_none()));
+
+        let (status, _) = call(&addr, "DELETE", &format!("/sessions/{}", other.id), Some(&admin), "");
+        assert_eq!(status, 200);
+        let (status, _) = call(&addr, "DELETE", &format!("/sessions/{}", other.id), Some(&admin), "");
+        assert_eq!(status, 404);
+        let (status, _) = call(&addr, "GET", "/reviews", Some(&revoked), "");
+        assert_eq!(status, 401);
+        let (status, _) = call(&addr, "GET", "/reviews", Some(&admin), "");
+        assert_eq!(status, 200);
+    }
+
+    #[test]
+    fn test_each_route_requires_its_scope() {
+        let user = fixture("route admin", "Route Burger");
+        let routes = [
+            ("GET", "/users", "", Scope::Manage, 200),
+            ("GET", "/sessions", "", Scope::Manage, 200),
+            ("GET", "/reviews", "", Scope::Read, 200),
+            ("GET", "/establishments", "", Scope::Read, 200),
+            ("GET", "/establishments/Route%20Burger/reviews", "", Scope::Read, 200),
+            ("GET", "/establishments/Route%20Burger/stats", "", Scope::Read, 200),
+            ("POST", "/establishments/Route%20Bistrot/reviews", r#"{"comment": "Bon", "grade": 4}"#, Scope::Review, 201),
+            ("POST", "/establishments/Route%20Burger/reviews/Route%20Burger%20a/votes", r#"{"helpful": true}"#, Scope::Vote, 200),
+            ("DELETE", "/establishments/Route%20Burger/reviews/Route%20Burger%20b", "", Scope::Delete, 200),
+        ];
+        let addr = start(2 * routes.len());
+
+        for (method, path, body, scope, status) in routes {
+            let others: Vec<Scope> = Scope::iter().filter(|other| *other != scope).collect();
+            let without = token::create(&user, "autres", others, 1).unwrap();
+            assert_eq!(call(&addr, method, path, Some(&without), body).0, 403, "{} {} without {}", method, path, scope);
+
+            let with = token::create(&user, "portée", vec![scope], 1).unwrap();
+            assert_eq!(call(&addr, method, path, Some(&with), body).0, status, "{} {} with {}", method, path, scope);
+        }
+    }
+}
@@ -0,0 +1,604 @@
+use crate::i18n::t;
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use derive_more::Display;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+use strum::EnumIter;
+
+static AUDIT_FILE: &str = "audit.log";
+/// Kept apart from the log so that entries removed from its end can be noticed
+static AUDIT_HEAD_FILE: &str = "audit.head";
+/// Secret key of the chain, to be kept where those who can write the log cannot read it
+static AUDIT_KEY_FILE: &str = "audit.key";
+static AUDIT_KEY_ENV: &str = "SLH_AUDIT_KEY_FILE";
+static GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+const KEY_SIZE: usize = 32;
+
+pub static AUDIT: Lazy<Mutex<AuditLog>> = Lazy::new(|| {
+    // Tests must not pollute the real audit log
+    if cfg!(test) {
+        Mutex::new(AuditLog::in_memory())
+    } else {
+        let key_path = env::var(AUDIT_KEY_ENV).unwrap_or_else(|_| AUDIT_KEY_FILE.to_string());
+        Mutex::new(AuditLog::open(AUDIT_FILE, AUDIT_HEAD_FILE, &key_path))
+    }
+});
+
+/// HMAC-SHA256 of the concatenated `parts`, in hexadecimal
+fn hmac(key: &[u8], parts: &[&[u8]]) -> String {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.map(|byte| byte ^ 0x36));
+    for part in parts {
+        inner.update(part);
+    }
+    let mut outer = Sha256::new();
+    outer.update(block.map(|byte| byte ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn random_key() -> Vec<u8> {
+    (0..KEY_SIZE).map(|_| rand::random::<u8>()).collect()
+}
+
+/// Read the key of the log, creating it if there is none yet. Returns whether it was just created.
+fn load_key(path: &str) -> anyhow::Result<(Vec<u8>, bool)> {
+    if let Ok(hex) = fs::read_to_string(path) {
+        let key = (0..hex.trim().len())
+            .step_by(2)
+            .map(|i| hex.trim().get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .filter(|key| key.len() == KEY_SIZE)
+            .ok_or_else(|| anyhow::anyhow!(t!("error.audit_key", path)))?;
+        return Ok((key, false));
+    }
+
+    let key = random_key();
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).with_context(|| t!("error.audit_key", path))?;
+    writeln!(file, "{}", key.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())?;
+    Ok((key, true))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Display, EnumIter)]
+pub enum AuditAction {
+    #[display(fmt = "{}", "t!(\"audit.login\")")]
+    Login,
+
+    #[display(fmt = "{}", "t!(\"audit.failed_login\")")]
+    FailedLogin,
+
+    #[display(fmt = "{}", "t!(\"audit.register\")")]
+    Register,
+
+    #[display(fmt = "{}", "t!(\"audit.add_review\")")]
+    AddReview,
+
+    #[display(fmt = "{}", "t!(\"audit.flag_review\")")]
+    FlagReview,
+
+    #[display(fmt = "{}", "t!(\"audit.edit_review\")")]
+    EditReview,
+
+    #[display(fmt = "{}", "t!(\"audit.delete_review\")")]
+    DeleteReview,
+
+    #[display(fmt = "{}", "t!(\"audit.hide_review\")")]
+    HideReview,
+
+    #[display(fmt = "{}", "t!(\"audit.change_role\")")]
+    ChangeRole,
+
+    #[display(fmt = "{}", "t!(\"audit.toggle_account\")")]
+    ToggleAccount,
+
+    #[display(fmt = "{}", "t!(\"audit.dispute\")")]
+    Dispute,
+
+    #[display(fmt = "{}", "t!(\"audit.categorize\")")]
+    Categorize,
+
+    #[display(fmt = "{}", "t!(\"audit.create_token\")")]
+    CreateToken,
+
+    #[display(fmt = "{}", "t!(\"audit.revoke_token\")")]
+    RevokeToken,
+
+    #[display(fmt = "{}", "t!(\"audit.access_denied\")")]
+    AccessDenied,
+
+    #[display(fmt = "{}", "t!(\"audit.authorization_error\")")]
+    AuthorizationError,
+
+    #[display(fmt = "{}", "t!(\"audit.vote\")")]
+    Vote,
+
+    #[display(fmt = "{}", "t!(\"audit.import_policy\")")]
+    ImportPolicy,
+
+    #[display(fmt = "{}", "t!(\"audit.revoke_session\")")]
+    RevokeSession,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Display)]
+#[display(
+    fmt = "[{}] {} - {}: {}",
+    "timestamp.format(\"%Y-%m-%d %H:%M:%S\")",
+    action,
+    user,
+    detail
+)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub user: String,
+    pub action: AuditAction,
+    pub detail: String,
+    prev_hash: String,
+    hash: String,
+}
+
+impl AuditEntry {
+    fn new(key: &[u8], prev_hash: &str, user: &str, action: AuditAction, detail: &str) -> Self {
+        let mut entry = Self {
+            timestamp: Utc::now(),
+            user: user.to_string(),
+            action,
+            detail: detail.to_string(),
+            prev_hash: prev_hash.to_string(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash(key);
+        entry
+    }
+
+    /// Content of the entry chained with the hash of the previous entry
+    fn content(&self) -> Vec<u8> {
+        let mut content = Vec::new();
+        content.extend(self.prev_hash.as_bytes());
+        content.extend(self.timestamp.to_rfc3339().as_bytes());
+        for field in [&self.user, &format!("{:?}", self.action), &self.detail] {
+            // Length prefix so that fields cannot be shifted into one another
+            content.extend((field.len() as u64).to_be_bytes());
+            content.extend(field.as_bytes());
+        }
+        content
+    }
+
+    /// Keyed hash of the entry, which cannot be recomputed without the key of the log
+    fn compute_hash(&self, key: &[u8]) -> String {
+        hmac(key, &[&self.content()])
+    }
+
+    /// Unkeyed hash of the entries written before the log had a key
+    fn legacy_hash(&self) -> String {
+        Sha256::digest(self.content()).iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+/// Criteria used to select entries of the audit log, `None` matches everything
+#[derive(Default)]
+pub struct AuditFilter {
+    pub user: Option<String>,
+    pub action: Option<AuditAction>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        let date = entry.timestamp.date_naive();
+        self.user.as_ref().is_none_or(|user| &entry.user == user)
+            && self.action.is_none_or(|action| entry.action == action)
+            && self.from.is_none_or(|from| date >= from)
+            && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+/// Number of entries of the log and hash of the last one, as of the last write, authenticated
+/// by the key so that the log cannot be cut short along with its head
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct Head {
+    count: usize,
+    hash: String,
+    #[serde(default)]
+    mac: String,
+}
+
+impl Head {
+    fn new(key: &[u8], count: usize, hash: &str) -> Self {
+        Self {
+            count,
+            hash: hash.to_string(),
+            mac: Self::compute_mac(key, count, hash),
+        }
+    }
+
+    fn compute_mac(key: &[u8], count: usize, hash: &str) -> String {
+        hmac(key, &[&(count as u64).to_be_bytes(), hash.as_bytes()])
+    }
+
+    fn is_authentic(&self, key: &[u8]) -> bool {
+        self.mac == Self::compute_mac(key, self.count, &self.hash)
+    }
+}
+
+/// Append-only log of security-relevant events, each entry being chained to the previous
+/// one by a hash keyed with a secret kept apart, so that any alteration can be detected
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    head_path: Option<PathBuf>,
+    key: Vec<u8>,
+    entries: Vec<AuditEntry>,
+    /// Index of the first line which could not be read, later lines being skipped
+    unreadable: Option<usize>,
+    head: Option<Head>,
+    /// Size of the file as last read or written, another process having appended to it if it changed
+    size: u64,
+}
+
+impl AuditLog {
+    /// Load the log, an unreadable line or key being reported by `verify` rather than failing.
+    /// A log written before it had a key is chained again with the new key, if still intact.
+    fn open(path: &str, head_path: &str, key_path: &str) -> Self {
+        let (key, created) = match load_key(key_path) {
+            Ok(key) => key,
+            Err(e) => {
+                // Without its key the log cannot be verified, a random one reports it as altered
+                eprintln!("{:#}", e);
+                (random_key(), false)
+            }
+        };
+
+        let mut log = Self {
+            path: Some(PathBuf::from(path)),
+            head_path: Some(PathBuf::from(head_path)),
+            key,
+            entries: Vec::new(),
+            unreadable: None,
+            head: None,
+            size: 0,
+        };
+        log.load();
+
+        if created && !log.entries.is_empty() {
+            if let Err(e) = log.rechain() {
+                eprintln!("{:#}", e);
+            }
+        }
+        log
+    }
+
+    fn in_memory() -> Self {
+        Self {
+            path: None,
+            head_path: None,
+            key: random_key(),
+            entries: Vec::new(),
+            unreadable: None,
+            head: None,
+            size: 0,
+        }
+    }
+
+    /// Read the entries and head from the files
+    fn load(&mut self) {
+        let (Some(path), Some(head_path)) = (&self.path, &self.head_path) else {
+            return;
+        };
+
+        self.entries.clear();
+        self.unreadable = None;
+        self.size = fs::metadata(path).map_or(0, |metadata| metadata.len());
+        if let Ok(file) = File::open(path) {
+            for (i, line) in BufReader::new(file).lines().enumerate() {
+                match line.ok().and_then(|line| serde_json::from_str(&line).ok()) {
+                    Some(entry) => self.entries.push(entry),
+                    None => {
+                        self.unreadable = Some(i);
+                        break;
+                    }
+                }
+            }
+        }
+        self.head = fs::read_to_string(head_path)
+            .ok()
+            .and_then(|head| serde_json::from_str(&head).ok());
+    }
+
+    /// Read the files again if another process wrote to the log since
+    pub fn refresh(&mut self) {
+        if let Some(path) = &self.path {
+            if fs::metadata(path).map_or(0, |metadata| metadata.len()) != self.size {
+                self.load();
+            }
+        }
+    }
+
+    /// Chain again with the key the entries of a log written before it had one,
+    /// provided their unkeyed chain is intact
+    fn rechain(&mut self) -> anyhow::Result<()> {
+        let mut prev_hash = GENESIS_HASH.to_string();
+        for entry in &self.entries {
+            if self.unreadable.is_some() || entry.prev_hash != prev_hash || entry.hash != entry.legacy_hash() {
+                anyhow::bail!(t!("error.audit_rechain"));
+            }
+            prev_hash = entry.hash.clone();
+        }
+
+        let mut prev_hash = GENESIS_HASH.to_string();
+        for entry in &mut self.entries {
+            entry.prev_hash = prev_hash;
+            entry.hash = entry.compute_hash(&self.key);
+            prev_hash = entry.hash.clone();
+        }
+        let lines: Vec<String> = self.entries.iter().map(serde_json::to_string).collect::<Result<_, _>>()?;
+        if let Some(path) = &self.path {
+            fs::write(path, lines.join("\n") + "\n").with_context(|| t!("error.audit_write"))?;
+            self.size = fs::metadata(path)?.len();
+        }
+        self.write_head(Head::new(&self.key, self.entries.len(), &prev_hash))
+    }
+
+    fn write_head(&mut self, head: Head) -> anyhow::Result<()> {
+        if let Some(path) = &self.head_path {
+            fs::write(path, serde_json::to_string(&head)?).with_context(|| t!("error.audit_head"))?;
+        }
+        self.head = Some(head);
+        Ok(())
+    }
+
+    pub fn record(&mut self, user: &str, action: AuditAction, detail: &str) -> anyhow::Result<()> {
+        // The lock, released when the file is closed, keeps processes from appending at the same
+        // time, each one chaining its entry to the last one written whoever wrote it
+        let mut file = match &self.path {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| t!("error.audit_open"))?;
+                file.lock().with_context(|| t!("error.audit_open"))?;
+                self.refresh();
+                Some(file)
+            }
+            None => None,
+        };
+
+        let prev_hash = self
+            .entries
+            .last()
+            .map_or(GENESIS_HASH, |entry| entry.hash.as_str());
+        let entry = AuditEntry::new(&self.key, prev_hash, user, action, detail);
+
+        if let Some(file) = &mut file {
+            writeln!(file, "{}", serde_json::to_string(&entry)?).with_context(|| t!("error.audit_write"))?;
+            self.size = file.metadata()?.len();
+        }
+
+        // A log which could not be fully read keeps its old head, so that the damage stays reported
+        if self.unreadable.is_none() {
+            self.write_head(Head::new(&self.key, self.entries.len() + 1, &entry.hash))?;
+        }
+
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Check the hash chain and that it still ends where it did at the last write,
+    /// returns the index of the first altered, unreadable or missing entry if any
+    pub fn verify(&self) -> Result<(), usize> {
+        let mut prev_hash = GENESIS_HASH;
+        for (i, entry) in self.entries.iter().enumerate() {
+            if entry.prev_hash != prev_hash || entry.hash != entry.compute_hash(&self.key) {
+                return Err(i);
+            }
+            prev_hash = &entry.hash;
+        }
+        if let Some(i) = self.unreadable {
+            return Err(i);
+        }
+
+        // Every write keeps the head, its absence may hide entries removed from the end
+        match &self.head {
+            None if !self.entries.is_empty() => Err(self.entries.len()),
+            None => Ok(()),
+            Some(head) if !head.is_authentic(&self.key) || head.count != self.entries.len() => {
+                Err(head.count.min(self.entries.len()))
+            }
+            Some(head) if head.count > 0 && self.entries[head.count - 1].hash != head.hash => Err(head.count - 1),
+            Some(..) => Ok(()),
+        }
+    }
+
+    pub fn entries(&self, filter: &AuditFilter) -> Vec<AuditEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Record an event in the global audit log. A failure to write is reported
+/// but does not prevent the audited operation.
+pub fn record(user: &str, action: AuditAction, detail: &str) {
+    if let Err(e) = AUDIT.lock().unwrap().record(user, action, detail) {
+        eprintln!("{:#}", e);
+    }
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_log() -> AuditLog {
+        let mut log = AuditLog::in_memory();
+        log.record("Toto", AuditAction::Login, "").unwrap();
+        log.record("Toto", AuditAction::AddReview, "McDonalds").unwrap();
+        log.record("Bastian", AuditAction::AccessDenied, "delete sur any").unwrap();
+        log
+    }
+
+    #[test]
+    fn test_untouched_log_is_valid() {
+        assert_eq!(sample_log().verify(), Ok(()));
+        assert_eq!(AuditLog::in_memory().verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_altered_entry_is_detected() {
+        let mut log = sample_log();
+        log.entries[1].detail = "Bistrot des Lutins".to_string();
+        assert_eq!(log.verify(), Err(1));
+
+        let mut log = sample_log();
+        log.entries.remove(1);
+        assert_eq!(log.verify(), Err(1));
+
+        let mut log = sample_log();
+        log.entries.pop();
+        assert_eq!(log.verify(), Err(2));
+
+        let mut log = sample_log();
+        log.head = None;
+        assert_eq!(log.verify(), Err(3));
+    }
+
+    #[test]
+    fn test_hmac_matches_rfc_4231() {
+        assert_eq!(
+            hmac(b"Jefe", &[b"what do ya want ", b"for nothing?"]),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_chain_cannot_be_forged_without_the_key() {
+        let mut log = sample_log();
+        let forger = random_key();
+        log.entries[1].detail = "Bistrot des Lutins".to_string();
+        let mut prev_hash = GENESIS_HASH.to_string();
+        for entry in &mut log.entries {
+            entry.prev_hash = prev_hash;
+            entry.hash = entry.compute_hash(&forger);
+            prev_hash = entry.hash.clone();
+        }
+        log.head = Some(Head::new(&forger, 3, &prev_hash));
+        assert_eq!(log.verify(), Err(0));
+    }
+
+    /// Paths of a log, its head and its key in the temporary directory, removed beforehand
+    fn temp_files(name: &str) -> [String; 3] {
+        ["log", "head", "key"].map(|ext| {
+            let path = std::env::temp_dir().join(format!("audit-{}-{}.{}", name, std::process::id(), ext));
+            let _ = fs::remove_file(&path);
+            path.to_str().unwrap().to_string()
+        })
+    }
+
+    #[test]
+    fn test_processes_append_to_the_same_chain() {
+        let [path, head_path, key_path] = temp_files("shared");
+
+        let mut first = AuditLog::open(&path, &head_path, &key_path);
+        let mut second = AuditLog::open(&path, &head_path, &key_path);
+        first.record("a", AuditAction::Login, "").unwrap();
+        second.record("b", AuditAction::Login, "").unwrap();
+        first.record("a", AuditAction::AddReview, "McDonalds").unwrap();
+
+        let log = AuditLog::open(&path, &head_path, &key_path);
+        assert_eq!(log.entries(&AuditFilter::default()).len(), 3);
+        assert_eq!(log.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_unkeyed_log_is_chained_with_the_new_key() {
+        let [path, head_path, key_path] = temp_files("legacy");
+        let mut prev_hash = GENESIS_HASH.to_string();
+        let mut lines = Vec::new();
+        for user in ["a", "b"] {
+            let mut entry = AuditEntry::new(&[], &prev_hash, user, AuditAction::Login, "");
+            entry.hash = entry.legacy_hash();
+            prev_hash = entry.hash.clone();
+            lines.push(serde_json::to_string(&entry).unwrap());
+        }
+        fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        let log = AuditLog::open(&path, &head_path, &key_path);
+        assert_eq!(log.entries(&AuditFilter::default()).len(), 2);
+        assert_eq!(log.verify(), Ok(()));
+        assert_eq!(AuditLog::open(&path, &head_path, &key_path).verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_damaged_file_is_reported() {
+        let [path, head_path, key_path] = temp_files("damaged");
+        let (path, head_path, key_path) = (path.as_str(), head_path.as_str(), key_path.as_str());
+
+        let mut log = AuditLog::open(path, head_path, key_path);
+        for user in ["a", "b", "c"] {
+            log.record(user, AuditAction::Login, "").unwrap();
+        }
+        assert_eq!(AuditLog::open(path, head_path, key_path).verify(), Ok(()));
+
+        let lines: Vec<String> = fs::read_to_string(path).unwrap().lines().map(str::to_string).collect();
+        fs::write(path, format!("{}\n{}\n", lines[0], lines[1])).unwrap();
+        assert_eq!(AuditLog::open(path, head_path, key_path).verify(), Err(2));
+
+        fs::write(path, format!("{}\n{{tampered\n{}\n", lines[0], lines[2])).unwrap();
+        let log = AuditLog::open(path, head_path, key_path);
+        assert_eq!(log.verify(), Err(1));
+        assert_eq!(log.entries(&AuditFilter::default()).len(), 1);
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(head_path).unwrap();
+        fs::remove_file(key_path).unwrap();
+    }
+
+    #[test]
+    fn test_filter_entries() {
+        let log = sample_log();
+        let today = Utc::now().date_naive();
+
+        let by_user = AuditFilter {
+            user: Some("Toto".to_string()),
+            ..Default::default()
+        };
+        let by_action = AuditFilter {
+            action: Some(AuditAction::AccessDenied),
+            ..Default::default()
+        };
+        let by_date = AuditFilter {
+            from: today.succ_opt(),
+            ..Default::default()
+        };
+
+        assert_eq!(log.entries(&AuditFilter::default()).len(), 3);
+        assert_eq!(log.entries(&by_user).len(), 2);
+        assert_eq!(log.entries(&by_action).len(), 1);
+        assert!(log.entries(&by_date).is_empty());
+    }
+}
@@ -0,0 +1,594 @@
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use futures::executor::block_on;
+use std::{env, fs, io::{self, BufRead}, process::ExitCode};
+use crate::api::{self, DEFAULT_ADDR};
+use crate::audit::AuditFilter;
+use crate::db::Pagination;
+use crate::i18n::{self, t, Lang};
+use crate::service::{self, ServiceError, ServiceResult};
+use crate::output::{self, Account, Format, OpenSession, Tabular};
+use crate::search::{ReviewQuery, SortOrder};
+use crate::utils::authorization::{check_live_policy, check_policy, parse_cases, parse_role, CASES_FILE};
+use crate::utils::input_validation::normalize_name;
+use crate::{Criterion, ReviewStatus, Role, Scores, User};
+
+static PASSWORD_ENV: &str = "SLH_PASSWORD";
+
+// Exit statuses of the subcommands, 2 is also what clap returns for invalid arguments
+const EXIT_INVALID: u8 = 2;
+const EXIT_AUTHENTICATION: u8 = 3;
+const EXIT_DENIED: u8 = 4;
+const EXIT_NOT_FOUND: u8 = 5;
+
+#[derive(Parser)]
+#[command(version, about = t!("cli.about"))]
+pub struct Cli {
+    #[command(flatten)]
+    pub options: Options,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Args)]
+pub struct Options {
+    #[arg(long, global = true, value_parser = parse_name, help = t!("cli.user_option"))]
+    pub user: Option<String>,
+
+    #[arg(long, global = true, value_enum, default_value_t = Format::Table, help = t!("cli.format"))]
+    pub format: Format,
+
+    #[arg(long, global = true, value_enum, help = t!("cli.lang"))]
+    pub lang: Option<Lang>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand, about = t!("cli.user"))]
+    User(UserCommand),
+
+    #[command(subcommand, about = t!("cli.review"))]
+    Review(ReviewCommand),
+
+    #[command(subcommand, about = t!("cli.session"))]
+    Session(SessionCommand),
+
+    #[command(about = t!("cli.serve"))]
+    Serve {
+        #[arg(long, default_value = DEFAULT_ADDR, help = t!("cli.serve.addr"))]
+        addr: String,
+    },
+
+    #[command(about = t!("cli.stats"))]
+    Stats {
+        #[arg(long, value_parser = parse_name)]
+        establishment: Option<String>,
+    },
+
+    #[command(about = t!("cli.category"))]
+    Category {
+        #[arg(value_parser = parse_name)]
+        establishment: String,
+
+        #[arg(long, value_parser = parse_name)]
+        category: Option<String>,
+    },
+
+    #[command(about = t!("cli.audit"))]
+    Audit {
+        #[arg(long, value_parser = parse_name, help = t!("cli.audit.actor"))]
+        actor: Option<String>,
+
+        #[arg(long, help = t!("cli.audit.from"))]
+        from: Option<NaiveDate>,
+
+        #[arg(long, help = t!("cli.audit.to"))]
+        to: Option<NaiveDate>,
+    },
+
+    #[command(about = t!("cli.check_policy"))]
+    CheckPolicy {
+        #[arg(long, help = t!("cli.check_policy.policy"))]
+        policy: Option<String>,
+
+        #[arg(long, default_value = CASES_FILE, help = t!("cli.check_policy.cases"))]
+        cases: String,
+
+        #[arg(long, requires = "policy", help = t!("cli.check_policy.import"))]
+        import: bool,
+    },
+}
+
+impl Command {
+    /// Whether the subcommand may change the database, which then has to be saved. The server
+    /// saves each change itself, and sessions are kept in a file of their own.
+    pub fn is_mutating(&self) -> bool {
+        !matches!(
+            self,
+            Command::User(UserCommand::List)
+                | Command::Review(ReviewCommand::List { .. } | ReviewCommand::Search { .. })
+                | Command::Session(..)
+                | Command::Serve { .. }
+                | Command::Stats { .. }
+                | Command::Audit { .. }
+                | Command::CheckPolicy { import: false, .. }
+        )
+    }
+}
+
+#[derive(Subcommand)]
+pub enum UserCommand {
+    #[command(about = t!("cli.user.add"))]
+    Add {
+        #[arg(value_parser = parse_name)]
+        name: String,
+
+        #[arg(long, value_parser = parse_name, help = t!("cli.user.add.owner"))]
+        owner: Option<String>,
+    },
+
+    #[command(about = t!("cli.user.list"))]
+    List,
+
+    #[command(about = t!("cli.user.role"))]
+    Role {
+        #[arg(value_parser = parse_name)]
+        name: String,
+
+        #[arg(help = t!("cli.user.role.role"))]
+        role: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SessionCommand {
+    #[command(about = t!("cli.session.list"))]
+    List,
+
+    #[command(about = t!("cli.session.revoke"))]
+    Revoke {
+        #[arg(help = t!("cli.session.revoke.id"))]
+        id: String,
+    },
+}
+
+#[derive(Args)]
+pub struct PageOptions {
+    #[arg(long, help = t!("cli.page.limit"))]
+    limit: Option<usize>,
+
+    #[arg(long, default_value_t = 0, help = t!("cli.page.offset"))]
+    offset: usize,
+}
+
+impl From<PageOptions> for Pagination {
+    fn from(page: PageOptions) -> Self {
+        Self {
+            offset: page.offset,
+            limit: page.limit,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum ReviewCommand {
+    #[command(about = t!("cli.review.add"))]
+    Add {
+        #[arg(long, value_parser = parse_name)]
+        establishment: String,
+
+        #[arg(long)]
+        comment: String,
+
+        #[arg(long, help = t!("cli.review.add.grade"))]
+        grade: Option<u8>,
+
+        #[arg(long = "score", value_parser = parse_score, help = t!("cli.review.add.score"))]
+        scores: Vec<(Criterion, u8)>,
+    },
+
+    #[command(about = t!("cli.review.list"))]
+    List {
+        #[arg(long, value_parser = parse_name)]
+        establishment: Option<String>,
+
+        #[arg(long, value_enum, help = t!("cli.review.list.sort"))]
+        sort: Option<SortOrder>,
+
+        #[command(flatten)]
+        page: PageOptions,
+    },
+
+    #[command(about = t!("cli.review.search"))]
+    Search {
+        #[arg(default_value = "", help = t!("cli.review.search.text"))]
+        text: String,
+
+        #[arg(long)]
+        min_grade: Option<u8>,
+
+        #[arg(long)]
+        max_grade: Option<u8>,
+
+        #[arg(long, help = t!("cli.review.search.from"))]
+        from: Option<NaiveDate>,
+
+        #[arg(long, help = t!("cli.review.search.to"))]
+        to: Option<NaiveDate>,
+
+        #[arg(long, value_enum, default_value_t = SortOrder::Newest)]
+        sort: SortOrder,
+
+        #[command(flatten)]
+        page: PageOptions,
+    },
+
+    #[command(about = t!("cli.review.vote"))]
+    Vote {
+        #[arg(long, value_parser = parse_name)]
+        establishment: String,
+
+        #[arg(long, value_parser = parse_name)]
+        reviewer: String,
+
+        #[arg(long, help = t!("cli.review.vote.unhelpful"))]
+        unhelpful: bool,
+    },
+
+    #[command(about = t!("cli.review.delete"))]
+    Delete {
+        #[arg(long, value_parser = parse_name)]
+        establishment: String,
+
+        #[arg(long, value_parser = parse_name)]
+        reviewer: String,
+    },
+}
+
+pub fn run(options: &Options, command: Command) -> ExitCode {
+    let result = match command {
+        Command::CheckPolicy { policy, cases, import } => {
+            return run_policy_check(options, policy.as_deref(), &cases, import)
+        }
+        Command::Serve { addr } => api::serve(&addr).map_err(ServiceError::Failed),
+        Command::User(command) => run_user(options, command),
+        Command::Review(command) => run_review(options, command),
+        Command::Session(command) => run_session(options, command),
+        Command::Stats { establishment } => run_stats(options, establishment.as_deref()),
+        Command::Category { establishment, category } => run_category(options, &establishment, category.as_deref()),
+        Command::Audit { actor, from, to } => run_audit(options, AuditFilter {
+            user: actor,
+            action: None,
+            from,
+            to,
+        }),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit_code(&e)
+        }
+    }
+}
+
+/// Language given with --lang, looked for before parsing so that the help can be written in it
+pub fn lang_arg(args: impl Iterator<Item = String>) -> Option<Lang> {
+    let mut args = args.skip_while(|arg| arg != "--lang" && !arg.starts_with("--lang="));
+    let value = match args.next()?.strip_prefix("--lang=") {
+        Some(value) => value.to_string(),
+        None => args.next()?,
+    };
+    Lang::from_str(&value, true).ok()
+}
+
+/// Compose a name argument the way stored names are
+fn parse_name(arg: &str) -> Result<String, String> {
+    Ok(normalize_name(arg))
+}
+
+/// Parse a `<criterion>=<grade>` argument
+fn parse_score(arg: &str) -> Result<(Criterion, u8), String> {
+    let (criterion, grade) = arg.split_once('=').ok_or(t!("error.invalid_value"))?;
+    let criterion = Criterion::from_str(criterion.trim(), true)?;
+    let grade = grade.trim().parse().map_err(|_| t!("error.invalid_value"))?;
+    Ok((criterion, grade))
+}
+
+fn exit_code(e: &ServiceError) -> ExitCode {
+    match e {
+        ServiceError::Authentication => ExitCode::from(EXIT_AUTHENTICATION),
+        ServiceError::Denied(..) => ExitCode::from(EXIT_DENIED),
+        ServiceError::NotFound(..) => ExitCode::from(EXIT_NOT_FOUND),
+        ServiceError::Invalid(..) => ExitCode::from(EXIT_INVALID),
+        ServiceError::Failed(..) => ExitCode::FAILURE,
+    }
+}
+
+fn read_password() -> ServiceResult<String> {
+    if let Ok(password) = env::var(PASSWORD_ENV) {
+        return Ok(password);
+    }
+
+    let mut line = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|e| ServiceError::Failed(e.into()))?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Authenticate the user given with --user
+fn login(options: &Options) -> ServiceResult<User> {
+    let name = options.user.as_deref().ok_or_else(|| ServiceError::Invalid(t!("error.user_option")))?;
+    let user = service::authenticate(name, &read_password()?)?;
+    i18n::apply_preference(user.lang);
+    Ok(user)
+}
+
+fn print_list<T: Tabular>(items: &[T], format: Format) -> ServiceResult<()> {
+    print!("{}", output::render(items, format)?);
+    Ok(())
+}
+
+fn run_user(options: &Options, command: UserCommand) -> ServiceResult<()> {
+    match command {
+        UserCommand::Add { name, owner } => {
+            let role = match owner {
+                Some(owned_establishment) => Role::Owner { owned_establishment },
+                None => Role::Reviewer,
+            };
+            service::register(&name, &read_password()?, role)?;
+            println!("{}", t!("msg.account_created", name));
+        }
+        UserCommand::List => {
+            let accounts: Vec<Account> = service::users(&login(options)?)?
+                .into_iter()
+                .map(Account::from)
+                .collect();
+            print_list(&accounts, options.format)?;
+        }
+        UserCommand::Role { name, role } => {
+            let role = parse_role(&role).map_err(|e| ServiceError::Invalid(e.to_string()))?;
+            let target = service::change_role(&login(options)?, &name, role)?;
+            println!("{}", t!("msg.role_changed", target.name));
+        }
+    }
+
+    Ok(())
+}
+
+fn run_review(options: &Options, command: ReviewCommand) -> ServiceResult<()> {
+    let user = login(options)?;
+    match command {
+        ReviewCommand::Add {
+            establishment,
+            comment,
+            grade,
+            scores,
+        } => {
+            let review = service::add_review(&user, &establishment, &comment, grade, Scores::from_iter(scores))?;
+            match review.status {
+                ReviewStatus::Flagged => println!("{}", t!("msg.review_flagged")),
+                _ => println!("{}", t!("msg.review_added")),
+            }
+        }
+        ReviewCommand::List { establishment, sort, page } => {
+            let mut reviews = match establishment {
+                Some(establishment) => service::establishment_reviews(&user, &establishment),
+                None => service::own_reviews(&user),
+            };
+            if let Some(sort) = sort {
+                service::sort_reviews(&mut reviews, sort);
+            }
+            print_list(&Pagination::from(page).apply(&reviews).items, options.format)?;
+        }
+        ReviewCommand::Search {
+            text,
+            min_grade,
+            max_grade,
+            from,
+            to,
+            sort,
+            page,
+        } => {
+            let query = ReviewQuery {
+                text,
+                min_grade,
+                max_grade,
+                from,
+                to,
+                sort,
+            };
+            let reviews = service::search_reviews(&user, &query);
+            print_list(&Pagination::from(page).apply(&reviews).items, options.format)?;
+        }
+        ReviewCommand::Vote {
+            establishment,
+            reviewer,
+            unhelpful,
+        } => {
+            let tally = service::vote(&user, &establishment, &reviewer, !unhelpful)?;
+            println!("{}", t!("msg.vote_recorded", tally));
+        }
+        ReviewCommand::Delete { establishment, reviewer } => {
+            service::delete_review(&user, &establishment, &reviewer)?;
+            println!("{}", t!("msg.review_deleted"));
+        }
+    }
+
+    Ok(())
+}
+
+fn run_session(options: &Options, command: SessionCommand) -> ServiceResult<()> {
+    let user = login(options)?;
+    match command {
+        SessionCommand::List => {
+            let sessions: Vec<OpenSession> = service::sessions(&user)?
+                .into_iter()
+                .map(OpenSession::from)
+                .collect();
+            print_list(&sessions, options.format)?;
+        }
+        SessionCommand::Revoke { id } => {
+            let session = service::revoke_session(&user, &id)?;
+            println!("{}", t!("msg.session_revoked", session.username));
+        }
+    }
+
+    Ok(())
+}
+
+fn run_stats(options: &Options, establishment: Option<&str>) -> ServiceResult<()> {
+    let user = login(options)?;
+    let stats = match establishment {
+        Some(establishment) => vec![service::establishment_stats(&user, establishment)?],
+        None => service::all_stats(&user),
+    };
+    print_list(&stats, options.format)
+}
+
+fn run_category(options: &Options, establishment: &str, category: Option<&str>) -> ServiceResult<()> {
+    service::set_category(&login(options)?, establishment, category)?;
+    match category {
+        Some(category) => println!("{}", t!("msg.category_set", establishment, category)),
+        None => println!("{}", t!("msg.category_removed", establishment)),
+    }
+    Ok(())
+}
+
+fn run_audit(options: &Options, filter: AuditFilter) -> ServiceResult<()> {
+    let (entries, altered) = service::audit_entries(&login(options)?, &filter)?;
+    // On the error output, so that the listing stays parsable
+    if let Some(i) = altered {
+        eprintln!("{}", t!("msg.audit_altered", i + 1));
+    }
+    print_list(&entries, options.format)
+}
+
+/// Run the regression cases against the policy of the database, or against a candidate file
+/// which replaces it when `import` is set and every case passes
+fn run_policy_check(options: &Options, policy: Option<&str>, cases: &str, import: bool) -> ExitCode {
+    let cases = match fs::read_to_string(cases)
+        .map_err(anyhow::Error::from)
+        .and_then(|csv| parse_cases(&csv))
+    {
+        Ok(cases) => cases,
+        Err(e) => {
+            eprintln!("{}", t!("error.read_cases", e));
+            return ExitCode::from(2);
+        }
+    };
+
+    let total = cases.len();
+    let checked = match policy {
+        Some(policy) if import => login(options).and_then(|user| service::import_policy(&user, policy, cases)),
+        Some(policy) => block_on(check_policy(policy, cases)).map_err(|e| ServiceError::Failed(e.into())),
+        None => block_on(check_live_policy(cases)).map_err(|e| ServiceError::Failed(e.into())),
+    };
+    let failures = match checked {
+        Ok(failures) => failures,
+        Err(ServiceError::Failed(e)) => {
+            eprintln!("{}", e);
+            return ExitCode::from(EXIT_INVALID);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return exit_code(&e);
+        }
+    };
+
+    for failure in &failures {
+        let case = &failure.case;
+        let actual = match &failure.actual {
+            Ok(decision) => decision.to_string(),
+            Err(e) => t!("msg.case_error", e),
+        };
+        println!(
+            "{}",
+            t!(
+                "msg.case_failure",
+                case.line,
+                case.user.name,
+                format!("{:?}", case.user.role),
+                case.act,
+                case.obj,
+                case.expected,
+                actual
+            )
+        );
+    }
+    println!("{}", t!("msg.cases_passed", total - failures.len(), total));
+    if import {
+        match failures.is_empty() {
+            true => println!("{}", t!("msg.policy_imported")),
+            false => eprintln!("{}", t!("error.policy_import_refused")),
+        }
+    }
+
+    if failures.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_subcommands() {
+        let cli = Cli::try_parse_from(["slh", "review", "list", "--establishment", "McDonalds", "--user", "Toto"]).unwrap();
+        assert_eq!(cli.options.user.as_deref(), Some("Toto"));
+        assert_eq!(cli.options.format, Format::Table);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Review(ReviewCommand::List { establishment: Some(ref e), .. })) if e == "McDonalds"
+        ));
+
+        assert!(Cli::try_parse_from(["slh", "review", "add", "--establishment", "McDonalds"]).is_err());
+        assert!(Cli::try_parse_from(["slh", "review", "add", "--establishment", "A", "--comment", "B", "--score", "food=4"]).is_ok());
+        assert!(Cli::try_parse_from(["slh", "review", "add", "--establishment", "A", "--comment", "B", "--score", "decor=4"]).is_err());
+        assert!(Cli::try_parse_from(["slh", "review", "list", "--establishment", "A", "--sort", "helpful"]).is_ok());
+        assert!(matches!(
+            Cli::try_parse_from(["slh", "review", "vote", "--establishment", "A", "--reviewer", "B", "--unhelpful"])
+                .unwrap()
+                .command,
+            Some(Command::Review(ReviewCommand::Vote { unhelpful: true, .. }))
+        ));
+        assert!(Cli::try_parse_from(["slh", "audit", "--from", "hier"]).is_err());
+        assert!(Cli::try_parse_from(["slh", "audit", "--format", "xml"]).is_err());
+        assert_eq!(Cli::try_parse_from(["slh", "--format", "json", "user", "list"]).unwrap().options.format, Format::Json);
+        assert!(Cli::try_parse_from(["slh"]).unwrap().command.is_none());
+        assert!(!Cli::try_parse_from(["slh", "check-policy"]).unwrap().command.unwrap().is_mutating());
+        assert!(Cli::try_parse_from(["slh", "check-policy", "--import"]).is_err());
+        assert!(Cli::try_parse_from(["slh", "session", "revoke"]).is_err());
+        assert!(!Cli::try_parse_from(["slh", "session", "revoke", "0123abcd"]).unwrap().command.unwrap().is_mutating());
+        assert!(Cli::try_parse_from(["slh", "check-policy", "--policy", "p.csv", "--import"]).unwrap().command.unwrap().is_mutating());
+    }
+
+    #[test]
+    fn test_lang_is_read_before_parsing() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().into_iter();
+        assert_eq!(lang_arg(args(&["slh", "review", "--lang", "en", "--help"])), Some(Lang::En));
+        assert_eq!(lang_arg(args(&["slh", "--lang=fr", "--help"])), Some(Lang::Fr));
+        assert_eq!(lang_arg(args(&["slh", "--lang"])), None);
+        assert_eq!(lang_arg(args(&["slh", "--help"])), None);
+    }
+
+    #[test]
+    fn test_missing_user_is_invalid() {
+        let options = Options {
+            user: None,
+            format: Format::Table,
+            lang: None,
+        };
+        assert!(matches!(login(&options), Err(ServiceError::Invalid(..))));
+        assert_eq!(exit_code(&ServiceError::Denied(String::new())), ExitCode::from(EXIT_DENIED));
+    }
+}
@@ -1,19 +1,93 @@
-use crate::{Review, Role, User};
+use crate::{Review, ReviewStatus, Role, User};
+use crate::token::ApiToken;
+use crate::i18n::t;
+use crate::index::{CommentIndex, ReviewKey};
+use crate::utils::policy_adapter::{self, PolicyRule};
+use crate::votes::{self, Tally, Vote};
 use anyhow::{anyhow, bail};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs::File, sync::Mutex};
+use std::{collections::{HashMap, HashSet}, fs::File, sync::Mutex};
 
 // DO NOT MODIFY THIS FILE!!!
 
 static DB_FILE: &str = "database.json";
-pub static DATABASE: Lazy<Mutex<Database>> =
-    Lazy::new(|| Mutex::new(Database::load().unwrap_or_default()));
+pub static DATABASE: Lazy<Mutex<Database>> = Lazy::new(|| {
+    // Tests start from an empty database instead of the real one, each creating what it needs
+    if cfg!(test) {
+        Mutex::new(Database::empty())
+    } else {
+        Mutex::new(Database::load().unwrap_or_default())
+    }
+});
+
+/// Window over a listing, starting at `offset` and holding at most `limit` items
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Pagination {
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl Pagination {
+    pub fn new(offset: usize, limit: usize) -> Self {
+        Self {
+            offset,
+            limit: Some(limit),
+        }
+    }
+
+    pub fn apply<T: Clone>(&self, items: &[T]) -> Page<T> {
+        let end = match self.limit {
+            Some(limit) => self.offset.saturating_add(limit).min(items.len()),
+            None => items.len(),
+        };
+        Page {
+            items: items.get(self.offset..end).unwrap_or_default().to_vec(),
+            offset: self.offset,
+            total: items.len(),
+        }
+    }
+}
+
+/// Items of a listing selected by a `Pagination`, along with the size of the whole listing
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub offset: usize,
+    pub total: usize,
+}
+
+impl<T> Page<T> {
+    pub fn has_previous(&self) -> bool {
+        self.offset > 0
+    }
+
+    pub fn has_next(&self) -> bool {
+        self.offset + self.items.len() < self.total
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct Database {
     users: HashMap<String, User>,
     reviews: Vec<Review>,
+    #[serde(default)]
+    policies: Vec<PolicyRule>,
+    #[serde(default)]
+    policy_version: u32,
+    /// Establishments whose reviews are under dispute
+    #[serde(default)]
+    disputes: Vec<String>,
+    /// Category of each establishment having one, e.g. "Restauration rapide"
+    #[serde(default)]
+    categories: HashMap<String, String>,
+    #[serde(default)]
+    tokens: Vec<ApiToken>,
+    /// Full-text index of the review comments, kept up to date with `reviews`
+    #[serde(default)]
+    comment_index: CommentIndex,
+    #[serde(default)]
+    votes: Vec<Vote>,
 }
 
 impl Database {
@@ -21,15 +95,82 @@ impl Database {
         Self {
             users: HashMap::new(),
             reviews: Vec::new(),
+            policies: Vec::new(),
+            policy_version: policy_adapter::POLICY_VERSION,
+            disputes: Vec::new(),
+            categories: HashMap::new(),
+            tokens: Vec::new(),
+            comment_index: CommentIndex::default(),
+            votes: Vec::new(),
+        }
+    }
+
+    /// Database without users nor reviews, with the default policy
+    fn empty() -> Self {
+        let mut db = Self::new();
+        db.set_default_policy();
+        db
+    }
+
+    /// Install the default policy. If it cannot be read the database is left without rules, which
+    /// denies everything, and marked as predating stored policies to get it on the next start.
+    pub fn set_default_policy(&mut self) {
+        match policy_adapter::default_policy() {
+            Ok(rules) => {
+                self.policies = rules;
+                self.policy_version = policy_adapter::POLICY_VERSION;
+            }
+            Err(e) => {
+                eprintln!("{}", t!("error.default_policy", format!("{:#}", e)));
+                self.policies = Vec::new();
+                self.policy_version = 0;
+            }
         }
     }
 
     fn load() -> Option<Self> {
         let file = File::open(DB_FILE).ok()?;
-        Some(
-            serde_json::from_reader(file)
-                .expect("le fichier de la base de donnée est corrompu ou invalide"),
-        )
+        let mut db: Self = serde_json::from_reader(file)
+            .unwrap_or_else(|e| panic!("{}", t!("error.corrupt_db", e)));
+
+        db.migrate_policies();
+        // Databases saved before the index existed get it built once
+        if db.comment_index.is_empty() {
+            db.comment_index = CommentIndex::build(&db.reviews);
+        }
+        Some(db)
+    }
+
+    /// Databases created before policies were stored here get the default policy file, those
+    /// whose policies use an older format have their rules rewritten for the current model.
+    /// If that fails, the stored rules are kept as they are to be migrated on the next start,
+    /// and no rule applies meanwhile.
+    fn migrate_policies(&mut self) {
+        if self.policy_version >= policy_adapter::POLICY_VERSION {
+            return;
+        }
+
+        let had_rules = !self.policies.is_empty();
+        let migrated = match had_rules {
+            true => policy_adapter::migrate(self.policy_version, self.policies.clone()),
+            false => policy_adapter::default_policy().map(|policies| (policies, Vec::new())),
+        };
+        let (policies, unconverted) = match migrated {
+            Ok(migrated) => migrated,
+            Err(e) => {
+                eprintln!("{}", t!("error.policy_migration", format!("{:#}", e)));
+                return;
+            }
+        };
+
+        if had_rules {
+            eprintln!("{}", t!("msg.policy_migrated", self.policy_version, policy_adapter::POLICY_VERSION));
+        }
+        for rule in unconverted {
+            eprintln!("{}", t!("msg.policy_rule_dropped", format!("{}, {}", rule.ptype, rule.rule.join(", "))));
+        }
+        self.policies = policies;
+        self.policy_version = policy_adapter::POLICY_VERSION;
     }
 
     pub fn save(&self) -> anyhow::Result<()> {
@@ -42,6 +183,12 @@ impl Database {
         self.users.get(name).cloned()
     }
 
+    pub fn get_users(&self) -> Vec<User> {
+        let mut users: Vec<User> = self.users.values().cloned().collect();
+        users.sort_by(|a, b| a.name.cmp(&b.name));
+        users
+    }
+
     pub fn get_review(&self, reviewer: &str, establishment: &str) -> Option<Review> {
         self.reviews
             .iter()
@@ -65,6 +212,23 @@ impl Database {
             .collect()
     }
 
+    /// Names of the establishments that were reviewed or have an owner
+    pub fn get_establishments(&self) -> Vec<String> {
+        let mut establishments: Vec<String> = self
+            .reviews
+            .iter()
+            .map(|review| review.establishment.clone())
+            .chain(self.users.values().filter_map(|user| match &user.role {
+                Role::Owner { owned_establishment } => Some(owned_establishment.clone()),
+                _ => None,
+            }))
+            .collect();
+        establishments.sort();
+        establishments.dedup();
+        establishments
+    }
+
+    #[allow(clippy::match_like_matches_macro)]
     pub fn get_owner_of(&self, estab: &str) -> Option<User> {
         self.users
             .values()
@@ -84,12 +248,12 @@ impl Database {
         } = user.role
         {
             if self.get_owner_of(owned_establishment).is_some() {
-                bail!("un propriétaire pour {} existe déjà", owned_establishment)
+                bail!(t!("error.owner_exists", owned_establishment))
             }
         }
 
         match self.users.get(&user.name) {
-            Some(..) => Err(anyhow!("un utilisateur nommé {} existe déjà", user.name)),
+            Some(..) => Err(anyhow!(t!("error.user_exists", user.name))),
             None => {
                 self.users.insert(user.name.clone(), user.clone());
                 Ok(())
@@ -97,14 +261,32 @@ impl Database {
         }
     }
 
+    /// Replace an existing user, e.g. to change their role
+    pub fn update_user(&mut self, user: &User) -> anyhow::Result<()> {
+        if !self.users.contains_key(&user.name) {
+            bail!(t!("error.no_such_user", user.name))
+        }
+
+        if let Role::Owner {
+            ref owned_establishment,
+        } = user.role
+        {
+            if let Some(owner) = self.get_owner_of(owned_establishment) {
+                if owner.name != user.name {
+                    bail!(t!("error.owner_exists", owned_establishment))
+                }
+            }
+        }
+
+        self.users.insert(user.name.clone(), user.clone());
+        Ok(())
+    }
+
     pub fn store_review(&mut self, review: &Review) -> anyhow::Result<()> {
         match self.get_review(&review.reviewer, &review.establishment) {
-            Some(..) => Err(anyhow!(
-                "un avis de {} sur {} existe déjà",
-                review.reviewer,
-                review.establishment
-            )),
+            Some(..) => Err(anyhow!(t!("error.review_exists", review.reviewer, review.establishment))),
             None => {
+                self.comment_index.insert(review);
                 self.reviews.push(review.clone());
                 Ok(())
             }
@@ -112,10 +294,147 @@ impl Database {
     }
 
     pub fn delete_review(&mut self, reviewer: &str, establishment: &str) {
+        if let Some(review) = self.get_review(reviewer, establishment) {
+            self.comment_index.remove(&review);
+            let key = ReviewKey::of(&review);
+            self.votes.retain(|vote| vote.review != key);
+        }
         self.reviews.retain(|review| {
             !(review.reviewer == reviewer && review.establishment == establishment)
         });
     }
+
+    /// Replace the content of an existing review
+    pub fn update_review(&mut self, review: &Review) -> anyhow::Result<()> {
+        match self
+            .reviews
+            .iter_mut()
+            .find(|r| r.reviewer == review.reviewer && r.establishment == review.establishment)
+        {
+            Some(stored) => {
+                self.comment_index.remove(stored);
+                self.comment_index.insert(review);
+                *stored = review.clone();
+                Ok(())
+            }
+            None => Err(anyhow!(t!("error.no_such_review", review.reviewer, review.establishment))),
+        }
+    }
+
+    pub fn set_review_status(&mut self, reviewer: &str, establishment: &str, status: ReviewStatus) {
+        self.reviews
+            .iter_mut()
+            .filter(|review| review.reviewer == reviewer && review.establishment == establishment)
+            .for_each(|review| review.status = status);
+    }
+
+    /// Reviews whose comment contains every word of `text`, see `CommentIndex::search`
+    pub fn search_comments(&self, text: &str) -> HashSet<ReviewKey> {
+        self.comment_index.search(text)
+    }
+
+    /// Record a vote, replacing the previous one of the same user on the same review
+    pub fn store_vote(&mut self, vote: Vote) {
+        self.votes
+            .retain(|stored| !(stored.voter == vote.voter && stored.review == vote.review));
+        self.votes.push(vote);
+    }
+
+    /// Votes received by each review having any
+    pub fn get_tallies(&self) -> HashMap<ReviewKey, Tally> {
+        votes::tally(&self.votes)
+    }
+
+    pub fn is_disputed(&self, establishment: &str) -> bool {
+        self.disputes.iter().any(|disputed| disputed == establishment)
+    }
+
+    pub fn set_disputed(&mut self, establishment: &str, disputed: bool) {
+        self.disputes.retain(|e| e != establishment);
+        if disputed {
+            self.disputes.push(establishment.to_string());
+        }
+    }
+
+    pub fn get_establishments_in(&self, category: &str) -> Vec<String> {
+        let mut establishments: Vec<String> = self
+            .categories
+            .iter()
+            .filter(|(_, c)| *c == category)
+            .map(|(establishment, _)| establishment.clone())
+            .collect();
+        establishments.sort();
+        establishments
+    }
+
+    /// Put an establishment in a category, or in none
+    pub fn set_category(&mut self, establishment: &str, category: Option<&str>) {
+        match category {
+            Some(category) => self.categories.insert(establishment.to_string(), category.to_string()),
+            None => self.categories.remove(establishment),
+        };
+    }
+
+    /// Tokens are looked up by id, which must then be unique
+    pub fn store_token(&mut self, token: &ApiToken) -> anyhow::Result<()> {
+        if self.tokens.iter().any(|stored| stored.id == token.id) {
+            bail!(t!("error.token_exists", token.id));
+        }
+        self.tokens.push(token.clone());
+        Ok(())
+    }
+
+    pub fn get_token(&self, id: &str) -> Option<ApiToken> {
+        self.tokens.iter().find(|token| token.id == id).cloned()
+    }
+
+    pub fn get_tokens_of(&self, owner: &str) -> Vec<ApiToken> {
+        self.tokens
+            .iter()
+            .filter(|token| token.owner == owner)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns whether the token existed and belonged to `owner`
+    pub fn delete_token(&mut self, owner: &str, id: &str) -> bool {
+        let count = self.tokens.len();
+        self.tokens.retain(|token| !(token.owner == owner && token.id == id));
+        self.tokens.len() != count
+    }
+
+    /// Rules of the policy, none while they are still in a format older than the model
+    pub fn get_policy_rules(&self) -> Vec<PolicyRule> {
+        match self.policy_version >= policy_adapter::POLICY_VERSION {
+            true => self.policies.clone(),
+            false => Vec::new(),
+        }
+    }
+
+    /// Replace the policy by rules written for the current model
+    pub fn set_policy_rules(&mut self, rules: Vec<PolicyRule>) {
+        self.policies = rules;
+        self.policy_version = policy_adapter::POLICY_VERSION;
+    }
+
+    /// Returns whether the rule was added, i.e. was not already present
+    pub fn store_policy_rule(&mut self, rule: &PolicyRule) -> bool {
+        if self.policies.contains(rule) {
+            return false;
+        }
+        self.policies.push(rule.clone());
+        true
+    }
+
+    /// Delete every rule matching the predicate, returns whether any was deleted
+    pub fn delete_policy_rules<F>(&mut self, predicate: F) -> bool
+        where
+            F: Fn(&PolicyRule) -> bool,
+    {
+        let count = self.policies.len();
+        self.policies.retain(|rule| !predicate(rule));
+        self.policies.len() != count
+    }
 }
 
 // Initialize the database to the default content provided by the `init` method
@@ -126,3 +445,38 @@ impl Default for Database {
         db
     }
 }
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pagination() {
+        let items: Vec<u8> = (0..25).collect();
+
+        let page = Pagination::new(0, 10).apply(&items);
+        assert_eq!(page.items, (0..10).collect::<Vec<_>>());
+        assert!(page.has_next() && !page.has_previous());
+
+        let page = Pagination::new(20, 10).apply(&items);
+        assert_eq!(page.items, (20..25).collect::<Vec<_>>());
+        assert!(!page.has_next() && page.has_previous());
+        assert_eq!(page.total, 25);
+
+        assert!(Pagination::new(30, 10).apply(&items).items.is_empty());
+        assert_eq!(Pagination::default().apply(&items).items.len(), 25);
+    }
+
+    #[test]
+    fn test_outdated_policy_applies_once_migrated() {
+        let mut db = Database::new();
+        db.policies = policy_adapter::parse_policy("p, Reviewer, *, review\n").unwrap();
+        db.policy_version = 1;
+        assert!(db.get_policy_rules().is_empty());
+
+        db.migrate_policies();
+        assert_eq!(db.get_policy_rules(), vec![PolicyRule::new("p", vec!["Reviewer".into(), "*".into(), "review".into(), "true".into()])]);
+    }
+}
@@ -0,0 +1,193 @@
+use clap::ValueEnum;
+use derive_more::Display;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env,
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
+use strum::EnumIter;
+
+static LANG_ENV: &str = "SLH_LANG";
+
+static CATALOGUES: Lazy<HashMap<Lang, HashMap<&'static str, &'static str>>> = Lazy::new(|| {
+    HashMap::from([
+        (Lang::Fr, parse_catalogue(include_str!("../locales/fr.txt"))),
+        (Lang::En, parse_catalogue(include_str!("../locales/en.txt"))),
+    ])
+});
+
+static CURRENT: AtomicU8 = AtomicU8::new(Lang::Fr as u8);
+// Language selected at startup, restored when the user whose preference applies logs out
+static STARTUP: AtomicU8 = AtomicU8::new(Lang::Fr as u8);
+// Set when the language was chosen on the command line, which then wins over user preferences
+static FORCED: AtomicBool = AtomicBool::new(false);
+
+/// Translate a message of the catalogue, replacing each `{}` by the next argument
+macro_rules! t {
+    ($key:expr) => {
+        $crate::i18n::translate($key, &[])
+    };
+    ($key:expr, $($arg:expr),+ $(,)?) => {
+        $crate::i18n::translate($key, &[$(&$arg as &dyn ::std::fmt::Display),+])
+    };
+}
+pub(crate) use t;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Display, EnumIter, ValueEnum)]
+#[repr(u8)]
+pub enum Lang {
+    #[display(fmt = "Français")]
+    Fr,
+
+    #[display(fmt = "English")]
+    En,
+}
+
+impl Lang {
+    /// Parse a language code such as `en` or a locale such as `en_US.UTF-8`
+    fn from_code(code: &str) -> Option<Self> {
+        match code.get(..2)?.to_ascii_lowercase().as_str() {
+            "fr" => Some(Lang::Fr),
+            "en" => Some(Lang::En),
+            _ => None,
+        }
+    }
+}
+
+/// Select the language from the command line if given, from the environment otherwise
+pub fn init(lang: Option<Lang>) {
+    FORCED.store(lang.is_some(), Ordering::Relaxed);
+    let lang = lang
+        .or_else(|| env::var(LANG_ENV).ok().and_then(|code| Lang::from_code(&code)))
+        .unwrap_or(Lang::Fr);
+    STARTUP.store(lang as u8, Ordering::Relaxed);
+    set(lang);
+}
+
+/// Switch to the language preferred by a user, unless one was chosen on the command line
+pub fn apply_preference(lang: Option<Lang>) {
+    if let Some(lang) = lang.filter(|_| !FORCED.load(Ordering::Relaxed)) {
+        set(lang);
+    }
+}
+
+/// Go back to the language selected at startup, once the user who preferred another one is gone
+pub fn reset() {
+    CURRENT.store(STARTUP.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+fn set(lang: Lang) {
+    CURRENT.store(lang as u8, Ordering::Relaxed);
+}
+
+pub fn current() -> Lang {
+    match CURRENT.load(Ordering::Relaxed) {
+        x if x == Lang::En as u8 => Lang::En,
+        _ => Lang::Fr,
+    }
+}
+
+fn parse_catalogue(content: &'static str) -> HashMap<&'static str, &'static str> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, message)| (key.trim(), message.trim()))
+        .collect()
+}
+
+pub fn translate(key: &str, args: &[&dyn fmt::Display]) -> String {
+    translate_in(current(), key, args)
+}
+
+/// Messages missing from a catalogue fall back to French, then to their key
+fn translate_in(lang: Lang, key: &str, args: &[&dyn fmt::Display]) -> String {
+    let template = CATALOGUES[&lang]
+        .get(key)
+        .or_else(|| CATALOGUES[&Lang::Fr].get(key))
+        .copied()
+        .unwrap_or(key);
+
+    let mut parts = template.split("{}");
+    let mut message = parts.next().unwrap_or_default().to_string();
+    let mut args = args.iter();
+    for part in parts {
+        if let Some(arg) = args.next() {
+            message.push_str(&arg.to_string());
+        }
+        message.push_str(part);
+    }
+    message
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_catalogues_are_complete() {
+        let fr = &CATALOGUES[&Lang::Fr];
+        let en = &CATALOGUES[&Lang::En];
+
+        for (key, message) in fr {
+            let translation = en.get(key).unwrap_or_else(|| panic!("{} n'est pas traduit en anglais", key));
+            assert_eq!(message.matches("{}").count(), translation.matches("{}").count(), "{}", key);
+        }
+        for key in en.keys() {
+            assert!(fr.contains_key(key), "{} n'existe qu'en anglais", key);
+        }
+    }
+
+    /// Every key used with `t!` in the sources must exist in the catalogue
+    #[test]
+    fn test_used_keys_exist() {
+        fn visit(dir: &Path, sources: &mut Vec<String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    visit(&path, sources);
+                } else if path.extension().is_some_and(|ext| ext == "rs") {
+                    sources.push(fs::read_to_string(path).unwrap());
+                }
+            }
+        }
+
+        let mut sources = Vec::new();
+        visit(Path::new("src"), &mut sources);
+        let usage = Regex::new(r#"t!\(\\?"([a-z0-9_.]+)\\?""#).unwrap();
+
+        let mut count = 0;
+        for source in &sources {
+            for key in usage.captures_iter(source).map(|c| c[1].to_string()) {
+                assert!(CATALOGUES[&Lang::Fr].contains_key(key.as_str()), "clé inconnue : {}", key);
+                count += 1;
+            }
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(translate_in(Lang::Fr, "menu.login", &[]), "Se connecter");
+        assert_eq!(translate_in(Lang::En, "menu.login", &[]), "Log in");
+        assert_eq!(translate_in(Lang::En, "msg.cases_passed", &[&3, &4]), "3/4 cases passed");
+        assert_eq!(translate_in(Lang::En, "no.such.key", &[]), "no.such.key");
+    }
+
+    #[test]
+    fn test_lang_from_code() {
+        assert_eq!(Lang::from_code("en_US.UTF-8"), Some(Lang::En));
+        assert_eq!(Lang::from_code("FR"), Some(Lang::Fr));
+        assert_eq!(Lang::from_code("de"), None);
+        assert_eq!(Lang::from_code(""), None);
+    }
+}
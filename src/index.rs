@@ -0,0 +1,131 @@
+use crate::search::fold;
+use crate::Review;
+use once_cell::sync::Lazy;
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+static STEMMER: Lazy<Stemmer> = Lazy::new(|| Stemmer::create(Algorithm::French));
+
+/// Identifies a review, a reviewer having at most one review per establishment
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ReviewKey {
+    pub reviewer: String,
+    pub establishment: String,
+}
+
+impl ReviewKey {
+    pub fn of(review: &Review) -> Self {
+        Self {
+            reviewer: review.reviewer.clone(),
+            establishment: review.establishment.clone(),
+        }
+    }
+}
+
+/// Stemmed and accent-folded words of a text, e.g. "Excellents cafés" gives "excellent" and "caf"
+pub fn terms(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| fold(&STEMMER.stem(&word.to_lowercase())))
+        .collect()
+}
+
+/// Inverted index from the terms of review comments to the reviews containing them
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CommentIndex {
+    terms: BTreeMap<String, BTreeSet<ReviewKey>>,
+}
+
+impl CommentIndex {
+    pub fn build(reviews: &[Review]) -> Self {
+        let mut index = Self::default();
+        for review in reviews {
+            index.insert(review);
+        }
+        index
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn insert(&mut self, review: &Review) {
+        for term in terms(&review.comment) {
+            self.terms.entry(term).or_default().insert(ReviewKey::of(review));
+        }
+    }
+
+    /// Remove a review, `review` having the comment it was indexed with
+    pub fn remove(&mut self, review: &Review) {
+        let key = ReviewKey::of(review);
+        for term in terms(&review.comment) {
+            if let Some(keys) = self.terms.get_mut(&term) {
+                keys.remove(&key);
+                if keys.is_empty() {
+                    self.terms.remove(&term);
+                }
+            }
+        }
+    }
+
+    /// Reviews whose comment has, for every word of `text`, a term starting with it
+    pub fn search(&self, text: &str) -> HashSet<ReviewKey> {
+        let mut found: Option<HashSet<ReviewKey>> = None;
+        for prefix in terms(text) {
+            let matching: HashSet<ReviewKey> = self
+                .terms
+                .range(prefix.clone()..)
+                .take_while(|(term, _)| term.starts_with(&prefix))
+                .flat_map(|(_, keys)| keys.iter().cloned())
+                .collect();
+            found = Some(match found {
+                Some(found) => found.intersection(&matching).cloned().collect(),
+                None => matching,
+            });
+        }
+        found.unwrap_or_default()
+    }
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(reviewer: &str) -> ReviewKey {
+        ReviewKey {
+            reviewer: reviewer.to_string(),
+            establishment: "Index Bistrot".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_search_stems_and_folds() {
+        let index = CommentIndex::build(&[
+            Review::new("Index Bistrot", "a", "Des cafés excellents", 5),
+            Review::new("Index Bistrot", "b", "Café froid, service lent", 2),
+        ]);
+
+        assert_eq!(index.search("CAFE"), HashSet::from([key("a"), key("b")]));
+        assert_eq!(index.search("excellent café"), HashSet::from([key("a")]));
+        assert_eq!(index.search("serv"), HashSet::from([key("b")]));
+        assert!(index.search("chocolat").is_empty());
+        assert!(index.search("").is_empty());
+    }
+
+    #[test]
+    fn test_index_is_maintained() {
+        let mut review = Review::new("Index Bistrot", "a", "Accueil chaleureux", 5);
+        let mut index = CommentIndex::build(std::slice::from_ref(&review));
+
+        index.remove(&review);
+        assert!(index.is_empty());
+
+        review.comment = "Accueil glacial".to_string();
+        index.insert(&review);
+        assert!(index.search("chaleureux").is_empty());
+        assert_eq!(index.search("glacial"), HashSet::from([key("a")]));
+    }
+}
@@ -1,24 +1,52 @@
+mod api;
+mod audit;
+mod cli;
 mod db;
+mod i18n;
+mod index;
+mod output;
+mod search;
+mod service;
+mod session;
+mod spam;
+mod stats;
+mod token;
 mod ui;
 mod utils;
+mod votes;
 
+use chrono::{DateTime, Utc};
+use clap::{Parser, ValueEnum};
 use db::{Database, DATABASE};
+use std::collections::BTreeMap;
+use std::process::ExitCode;
 use derive_more::Display;
+use i18n::{t, Lang};
+use utils::input_validation::normalize_name;
 use serde::{Deserialize, Serialize};
+use strum::EnumIter;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Hash)]
 struct User {
     name: String,
     password: String,
     role: Role,
+    /// A disabled account is denied every action
+    #[serde(default)]
+    disabled: bool,
+    /// Language of the interface, the one of the environment if not set
+    #[serde(default)]
+    lang: Option<Lang>,
 }
 
 impl User {
     fn new(name: &str, password: &str, role: Role) -> Self {
         Self {
-            name: name.to_string(),
+            name: normalize_name(name),
             password: password.to_string(),
             role,
+            disabled: false,
+            lang: None,
         }
     }
 
@@ -31,6 +59,11 @@ impl User {
         let db = DATABASE.lock().unwrap();
         db.get_user(username)
     }
+
+    fn update(&self) -> anyhow::Result<()> {
+        let mut db = DATABASE.lock().unwrap();
+        db.update_user(self)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Hash)]
@@ -38,34 +71,105 @@ impl User {
 enum Role {
     Reviewer,
     Owner { owned_establishment: String },
+    /// Handles reviews of the listed establishments and of those in the listed categories,
+    /// or of all establishments if both lists are empty
+    Moderator {
+        #[serde(default)]
+        establishments: Vec<String>,
+        #[serde(default)]
+        categories: Vec<String>,
+    },
     Admin,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+enum ReviewStatus {
+    #[default]
+    Published,
+    /// Hidden by moderation, only visible to its author and moderators
+    Hidden,
+    /// Held for moderation as likely spam, only visible to its author and moderators
+    Flagged,
+}
+
+/// Aspect of an establishment that can be graded on its own
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Display, EnumIter, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum Criterion {
+    #[display(fmt = "{}", "t!(\"criterion.food\")")]
+    Food,
+
+    #[display(fmt = "{}", "t!(\"criterion.service\")")]
+    Service,
+
+    #[display(fmt = "{}", "t!(\"criterion.price\")")]
+    Price,
+
+    #[display(fmt = "{}", "t!(\"criterion.ambience\")")]
+    Ambience,
+}
+
+/// Grades given to some of the criteria, in addition to the overall grade
+type Scores = BTreeMap<Criterion, u8>;
+
+fn format_scores(scores: &Scores) -> String {
+    if scores.is_empty() {
+        return String::new();
+    }
+    let scores: Vec<String> = scores
+        .iter()
+        .map(|(criterion, score)| format!("{} {}/5", criterion, score))
+        .collect();
+    format!(" ({})", scores.join(", "))
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Display)]
 #[display(
-    fmt = r#"Avis sur "{}", par {}: "{}", {}/5"#,
-    establishment,
-    reviewer,
-    comment,
-    grade
+    fmt = "{}{}",
+    "t!(\"review.display\", establishment, reviewer, comment, grade)",
+    "format_scores(scores)"
 )]
 struct Review {
     establishment: String,
     reviewer: String,
     comment: String,
     grade: u8,
+    #[serde(default)]
+    status: ReviewStatus,
+    #[serde(default)]
+    created_at: DateTime<Utc>,
+    /// Reviews written before criteria existed only have an overall grade
+    #[serde(default)]
+    scores: Scores,
 }
 
 impl Review {
     fn new(establishment: &str, reviewer: &str, comment: &str, grade: u8) -> Self {
         Self {
-            establishment: establishment.to_string(),
-            reviewer: reviewer.to_string(),
+            establishment: normalize_name(establishment),
+            reviewer: normalize_name(reviewer),
             comment: comment.to_string(),
             grade,
+            status: ReviewStatus::Published,
+            created_at: Utc::now(),
+            scores: Scores::new(),
         }
     }
 
+    fn with_scores(mut self, scores: Scores) -> Self {
+        self.scores = scores;
+        self
+    }
+
+    /// Overall grade of a review graded per criterion, the rounded mean of its scores
+    fn derived_grade(scores: &Scores) -> Option<u8> {
+        if scores.is_empty() {
+            return None;
+        }
+        let sum: u32 = scores.values().map(|&score| score as u32).sum();
+        Some(((sum as f64 / scores.len() as f64).round()) as u8)
+    }
+
     fn save(&self) -> anyhow::Result<()> {
         let mut db = DATABASE.lock().unwrap();
         db.store_review(self)
@@ -76,6 +180,17 @@ impl Review {
         db.delete_review(&self.reviewer, &self.establishment);
     }
 
+    fn update(&self) -> anyhow::Result<()> {
+        let mut db = DATABASE.lock().unwrap();
+        db.update_review(self)
+    }
+
+    fn set_status(&mut self, status: ReviewStatus) {
+        let mut db = DATABASE.lock().unwrap();
+        db.set_review_status(&self.reviewer, &self.establishment, status);
+        self.status = status;
+    }
+
     /// Get a review made by a reviewer for an establishment
     fn get(reviewer: &str, establishment: &str) -> Option<Self> {
         let db = DATABASE.lock().unwrap();
@@ -131,22 +246,43 @@ impl Database {
         ];
 
         for mut user in users {
-            user.password = utils::password::hash_password(&user.password.as_bytes());
+            user.password = utils::password::hash_password(user.password.as_bytes());
             self.store_user(&user).unwrap();
         }
 
         for review in reviews {
             self.store_review(&review).unwrap();
         }
+
+        self.set_default_policy();
     }
 }
 
-fn main() {
-    ui::start();
+fn main() -> ExitCode {
+    // The help is written while parsing, in the language given with --lang if any
+    i18n::init(cli::lang_arg(std::env::args()));
+    let cli = cli::Cli::parse();
+    i18n::init(cli.options.lang);
+    // Read-only subcommands must not create or rewrite the database file
+    let save = match &cli.command {
+        Some(command) => command.is_mutating(),
+        None => true,
+    };
+    let code = match cli.command {
+        Some(command) => cli::run(&cli.options, command),
+        None => {
+            ui::start();
+            ExitCode::SUCCESS
+        }
+    };
+
+    if save {
+        DATABASE
+            .lock()
+            .unwrap()
+            .save()
+            .unwrap_or_else(|e| panic!("{}", t!("error.save_db", e)));
+    }
 
-    DATABASE
-        .lock()
-        .unwrap()
-        .save()
-        .expect("impossible de sauvegarder la base de données");
+    code
 }
@@ -0,0 +1,269 @@
+use crate::audit::AuditEntry;
+use crate::i18n::t;
+use crate::stats::EstablishmentStats;
+use crate::session::Session;
+use crate::{Review, Role, User};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// How listings are printed by the subcommands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Format {
+    #[default]
+    #[value(help = t!("cli.format.table"))]
+    Table,
+    #[value(help = t!("cli.format.json"))]
+    Json,
+    #[value(help = t!("cli.format.csv"))]
+    Csv,
+}
+
+/// Something that can be listed as one row of a table
+pub trait Tabular: Serialize {
+    const HEADER: &'static [&'static str];
+
+    fn row(&self) -> Vec<String>;
+}
+
+impl Tabular for Review {
+    const HEADER: &'static [&'static str] =
+        &["establishment", "reviewer", "grade", "scores", "comment", "status", "created_at"];
+
+    fn row(&self) -> Vec<String> {
+        let scores: Vec<String> = self
+            .scores
+            .iter()
+            .map(|(criterion, grade)| format!("{:?}={}", criterion, grade).to_lowercase())
+            .collect();
+
+        vec![
+            self.establishment.clone(),
+            self.reviewer.clone(),
+            self.grade.to_string(),
+            scores.join(";"),
+            self.comment.clone(),
+            format!("{:?}", self.status),
+            self.created_at.to_rfc3339(),
+        ]
+    }
+}
+
+impl Tabular for AuditEntry {
+    const HEADER: &'static [&'static str] = &["timestamp", "user", "action", "detail"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.timestamp.to_rfc3339(),
+            self.user.clone(),
+            format!("{:?}", self.action),
+            self.detail.clone(),
+        ]
+    }
+}
+
+/// Public view of an account, leaving out its password hash
+#[derive(Serialize)]
+pub struct Account {
+    name: String,
+    role: Role,
+    disabled: bool,
+}
+
+impl From<User> for Account {
+    fn from(user: User) -> Self {
+        Self {
+            name: user.name,
+            role: user.role,
+            disabled: user.disabled,
+        }
+    }
+}
+
+impl Tabular for Account {
+    const HEADER: &'static [&'static str] = &["name", "role", "disabled"];
+
+    fn row(&self) -> Vec<String> {
+        let role = match &self.role {
+            Role::Reviewer => "Reviewer".to_string(),
+            Role::Owner { owned_establishment } => format!("Owner:{}", owned_establishment),
+            Role::Moderator {
+                establishments,
+                categories,
+            } if establishments.is_empty() && categories.is_empty() => "Moderator".to_string(),
+            Role::Moderator {
+                establishments,
+                categories,
+            } => {
+                let categories = categories.iter().map(|category| format!("#{}", category));
+                format!("Moderator:{}", establishments.iter().cloned().chain(categories).collect::<Vec<_>>().join(";"))
+            }
+            Role::Admin => "Admin".to_string(),
+        };
+        vec![self.name.clone(), role, self.disabled.to_string()]
+    }
+}
+
+/// Public view of a session, leaving out the hash of its secret value
+#[derive(Serialize)]
+pub struct OpenSession {
+    id: String,
+    user: String,
+    created_at: DateTime<Utc>,
+    last_activity: DateTime<Utc>,
+}
+
+impl From<Session> for OpenSession {
+    fn from(session: Session) -> Self {
+        Self {
+            id: session.id,
+            user: session.username,
+            created_at: session.created_at,
+            last_activity: session.last_activity,
+        }
+    }
+}
+
+impl Tabular for OpenSession {
+    const HEADER: &'static [&'static str] = &["id", "user", "created_at", "last_activity"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.user.clone(),
+            self.created_at.to_rfc3339(),
+            self.last_activity.to_rfc3339(),
+        ]
+    }
+}
+
+impl Tabular for EstablishmentStats {
+    const HEADER: &'static [&'static str] = &[
+        "establishment", "count", "mean", "median", "grade_1", "grade_2", "grade_3", "grade_4", "grade_5", "trend",
+        "criteria",
+    ];
+
+    fn row(&self) -> Vec<String> {
+        let decimal = |value: Option<f64>| value.map_or(String::new(), |value| format!("{:.2}", value));
+        let trend: Vec<String> = self
+            .trend
+            .iter()
+            .map(|period| format!("{}:{:.2}", period.month, period.mean))
+            .collect();
+        let criteria: Vec<String> = self
+            .criteria
+            .iter()
+            .map(|criterion| format!("{:?}:{:.2}", criterion.criterion, criterion.mean).to_lowercase())
+            .collect();
+
+        let mut row = vec![
+            self.establishment.clone(),
+            self.count.to_string(),
+            decimal(self.mean),
+            decimal(self.median),
+        ];
+        row.extend(self.distribution.iter().map(usize::to_string));
+        row.push(trend.join(";"));
+        row.push(criteria.join(";"));
+        row
+    }
+}
+
+pub fn render<T: Tabular>(items: &[T], format: Format) -> anyhow::Result<String> {
+    Ok(match format {
+        Format::Table => render_table(items),
+        Format::Json => serde_json::to_string_pretty(items)? + "\n",
+        Format::Csv => render_csv(items),
+    })
+}
+
+fn render_table<T: Tabular>(items: &[T]) -> String {
+    let header: Vec<String> = T::HEADER.iter().map(|name| name.to_uppercase()).collect();
+    let rows: Vec<Vec<String>> = items.iter().map(Tabular::row).collect();
+
+    let mut widths: Vec<usize> = header.iter().map(|name| name.chars().count()).collect();
+    for row in &rows {
+        for (width, field) in widths.iter_mut().zip(row) {
+            *width = (*width).max(field.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(field, width)| format!("{:<width$}", field, width = width))
+            .collect();
+        table.push_str(line.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+fn render_csv<T: Tabular>(items: &[T]) -> String {
+    let header = T::HEADER.iter().map(|name| name.to_string()).collect();
+    std::iter::once(header)
+        .chain(items.iter().map(Tabular::row))
+        .map(|row| row.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(",") + "\n")
+        .collect()
+}
+
+/// Quote a field if it contains a separator, a quote or a line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Criterion, Scores};
+
+    fn sample_reviews() -> Vec<Review> {
+        let scores = Scores::from([(Criterion::Food, 5), (Criterion::Service, 3)]);
+        vec![
+            Review::new("McDonalds", "Sire Debeugg", "À fuire, vraiment \"à fuire\"", 1),
+            Review::new("Triple R", "Toto", "Bien", 4).with_scores(scores),
+        ]
+    }
+
+    #[test]
+    fn test_render_csv() {
+        let csv = render(&sample_reviews(), Format::Csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "establishment,reviewer,grade,scores,comment,status,created_at");
+        assert!(lines[1].starts_with("McDonalds,Sire Debeugg,1,,\"À fuire, vraiment \"\"à fuire\"\"\",Published,"));
+        assert!(lines[2].starts_with("Triple R,Toto,4,food=5;service=3,Bien,Published,"));
+    }
+
+    #[test]
+    fn test_render_json() {
+        let json = render(&sample_reviews(), Format::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value[1]["reviewer"], "Toto");
+        assert_eq!(value[1]["grade"], 4);
+
+        let account = Account::from(User::new("Toto", "secret hash", Role::Admin));
+        let json = render(&[account], Format::Json).unwrap();
+        assert!(!json.contains("secret hash"));
+    }
+
+    #[test]
+    fn test_render_table() {
+        let table = render(&sample_reviews(), Format::Table).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("ESTABLISHMENT  REVIEWER      GRADE"));
+        assert!(lines[2].starts_with("Triple R       Toto          4"));
+    }
+}
@@ -0,0 +1,172 @@
+use crate::i18n::t;
+use crate::index::ReviewKey;
+use crate::votes::Tally;
+use crate::Review;
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use derive_more::Display;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use strum::EnumIter;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Lowercase a text and strip its accents, so that "Cafétéria" and "cafeteria" compare equal
+pub fn fold(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Whether `needle` appears anywhere in `haystack`, ignoring case and accents
+pub fn contains(haystack: &str, needle: &str) -> bool {
+    fold(haystack).contains(&fold(needle))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Display, EnumIter, ValueEnum)]
+pub enum SortOrder {
+    /// Most recent reviews first
+    #[default]
+    #[display(fmt = "{}", "t!(\"sort.newest\")")]
+    #[value(help = t!("cli.sort.newest"))]
+    Newest,
+
+    /// Oldest reviews first
+    #[display(fmt = "{}", "t!(\"sort.oldest\")")]
+    #[value(help = t!("cli.sort.oldest"))]
+    Oldest,
+
+    /// Best grades first
+    #[display(fmt = "{}", "t!(\"sort.best\")")]
+    #[value(help = t!("cli.sort.best"))]
+    Best,
+
+    /// Worst grades first
+    #[display(fmt = "{}", "t!(\"sort.worst\")")]
+    #[value(help = t!("cli.sort.worst"))]
+    Worst,
+
+    /// By establishment, then by reviewer
+    #[display(fmt = "{}", "t!(\"sort.establishment\")")]
+    #[value(help = t!("cli.sort.establishment"))]
+    Establishment,
+
+    /// Reviews voted the most helpful first
+    #[display(fmt = "{}", "t!(\"sort.helpful\")")]
+    #[value(help = t!("cli.sort.helpful"))]
+    Helpful,
+}
+
+/// Sort reviews, `tallies` giving the votes received by each review for `SortOrder::Helpful`
+pub fn sort(reviews: &mut [Review], order: SortOrder, tallies: &HashMap<ReviewKey, Tally>) {
+    match order {
+        SortOrder::Newest => reviews.sort_by_key(|review| Reverse(review.created_at)),
+        SortOrder::Oldest => reviews.sort_by_key(|review| review.created_at),
+        SortOrder::Best => reviews.sort_by_key(|review| Reverse(review.grade)),
+        SortOrder::Worst => reviews.sort_by_key(|review| review.grade),
+        SortOrder::Establishment => reviews.sort_by(|a, b| {
+            fold(&a.establishment)
+                .cmp(&fold(&b.establishment))
+                .then_with(|| a.reviewer.cmp(&b.reviewer))
+        }),
+        SortOrder::Helpful => reviews.sort_by_key(|review| {
+            let tally = tallies.get(&ReviewKey::of(review)).copied().unwrap_or_default();
+            (Reverse(tally.score()), Reverse(review.created_at))
+        }),
+    }
+}
+
+/// Criteria used to search reviews, `None` and an empty text matching everything
+#[derive(Default)]
+pub struct ReviewQuery {
+    /// Searched in the establishment name, and word by word in the comment
+    pub text: String,
+    pub min_grade: Option<u8>,
+    pub max_grade: Option<u8>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub sort: SortOrder,
+}
+
+impl ReviewQuery {
+    fn matches(&self, review: &Review, text: &str, comments: &HashSet<ReviewKey>) -> bool {
+        let date = review.created_at.date_naive();
+        (fold(&review.establishment).contains(text) || comments.contains(&ReviewKey::of(review)))
+            && self.min_grade.is_none_or(|min| review.grade >= min)
+            && self.max_grade.is_none_or(|max| review.grade <= max)
+            && self.from.is_none_or(|from| date >= from)
+            && self.to.is_none_or(|to| date <= to)
+    }
+
+    /// Keep the reviews matching the query, in the requested order, `comments`
+    /// being the reviews whose comment matches the text according to the index
+    pub fn apply(
+        &self,
+        reviews: Vec<Review>,
+        comments: &HashSet<ReviewKey>,
+        tallies: &HashMap<ReviewKey, Tally>,
+    ) -> Vec<Review> {
+        let text = fold(self.text.trim());
+        let mut reviews: Vec<Review> = reviews
+            .into_iter()
+            .filter(|review| self.matches(review, &text, comments))
+            .collect();
+
+        sort(&mut reviews, self.sort, tallies);
+        reviews
+    }
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::CommentIndex;
+
+    #[test]
+    fn test_fold_ignores_case_and_accents() {
+        assert_eq!(fold("Cafétéria du Coin"), "cafeteria du coin");
+        assert_eq!(fold("ÀÉÎÕÜ ç"), "aeiou c");
+        assert!(contains("Cafétéria du coin", "CAFET"));
+        assert!(contains("Bistrot des Lutins", "lutin"));
+        assert!(!contains("McDonalds", "burger"));
+    }
+
+    #[test]
+    fn test_query_filters_and_sorts() {
+        let reviews = vec![
+            Review::new("Cafétéria du coin", "a", "Médiocre.", 2),
+            Review::new("Bistrot des Lutins", "b", "Au top, café excellent", 5),
+            Review::new("McDonalds", "c", "À fuir", 1),
+        ];
+        let index = CommentIndex::build(&reviews);
+
+        let query = ReviewQuery {
+            text: "cafe".to_string(),
+            sort: SortOrder::Best,
+            ..Default::default()
+        };
+        let found = query.apply(reviews.clone(), &index.search(&query.text), &HashMap::new());
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].establishment, "Bistrot des Lutins");
+
+        let query = ReviewQuery {
+            max_grade: Some(2),
+            sort: SortOrder::Establishment,
+            ..Default::default()
+        };
+        let found = query.apply(reviews.clone(), &index.search(&query.text), &HashMap::new());
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].establishment, "Cafétéria du coin");
+
+        let tallies = HashMap::from([(ReviewKey::of(&reviews[2]), Tally { helpful: 3, unhelpful: 1 })]);
+        let query = ReviewQuery {
+            sort: SortOrder::Helpful,
+            ..Default::default()
+        };
+        let found = query.apply(reviews, &HashSet::new(), &tallies);
+        assert_eq!(found[0].establishment, "McDonalds");
+    }
+}
@@ -0,0 +1,759 @@
+use crate::audit::{self, AuditAction, AuditEntry, AuditFilter, AUDIT};
+use crate::db::DATABASE;
+use crate::index::ReviewKey;
+use crate::search::{self, ReviewQuery, SortOrder};
+use crate::session::Session;
+use crate::spam;
+use crate::stats::EstablishmentStats;
+use crate::i18n::t;
+use crate::utils::content_filter::{filter_comment, is_username_acceptable};
+use crate::utils::authorization::{self, can_access, is_authorized, CaseFailure, Context, Decision, PolicyCase};
+use crate::utils::input_validation::{is_name_valid, name_skeleton, normalize_name, is_number_in_range, is_password_valid, is_text_length_valid, PASS_DEFAULT_SCORE, REVIEW_MAX_GRADE, REVIEW_MAX_SIZE, REVIEW_MIN_GRADE, REVIEW_MIN_SIZE};
+use crate::utils::password::{checked_password, hash_password};
+use crate::votes::{Tally, Vote};
+use crate::{Review, ReviewStatus, Role, Scores, User};
+use derive_more::Display;
+use futures::executor::block_on;
+use inquire::validator::{ErrorMessage, Validation};
+use inquire::CustomUserError;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Why an operation was refused, so that each front-end can report it its own way
+#[derive(Debug, Display)]
+pub enum ServiceError {
+    #[display(fmt = "{}", "t!(\"error.wrong_credentials\")")]
+    Authentication,
+
+    #[display(fmt = "{}", _0)]
+    Denied(String),
+
+    #[display(fmt = "{}", _0)]
+    NotFound(String),
+
+    #[display(fmt = "{}", _0)]
+    Invalid(String),
+
+    #[display(fmt = "{}", _0)]
+    Failed(anyhow::Error),
+}
+
+impl std::error::Error for ServiceError {}
+
+impl From<anyhow::Error> for ServiceError {
+    fn from(e: anyhow::Error) -> Self {
+        ServiceError::Failed(e)
+    }
+}
+
+pub type ServiceResult<T> = Result<T, ServiceError>;
+
+/// Turn the outcome of an input validator into an error
+fn validated(validation: Result<Validation, CustomUserError>) -> ServiceResult<()> {
+    match validation {
+        Ok(Validation::Valid) => Ok(()),
+        Ok(Validation::Invalid(ErrorMessage::Custom(message))) => Err(ServiceError::Invalid(message)),
+        Ok(Validation::Invalid(ErrorMessage::Default)) => Err(ServiceError::Invalid(t!("error.invalid_value"))),
+        Err(e) => Err(ServiceError::Invalid(e.to_string())),
+    }
+}
+
+/// Check an authorization, a denial being reported with `message`
+fn authorize(user: &User, obj: &str, act: &str, ctx: &Context, message: &str) -> ServiceResult<()> {
+    match block_on(is_authorized(user, obj, act, ctx)) {
+        Ok(Decision::Allow) => Ok(()),
+        Ok(Decision::Deny) => Err(ServiceError::Denied(message.to_string())),
+        Err(e) => Err(ServiceError::Failed(e.into())),
+    }
+}
+
+/// Same as `authorize`, without auditing denials, to filter what is listed
+fn can_see(user: &User, obj: &str, act: &str, ctx: &Context) -> bool {
+    match block_on(can_access(user, obj, act, ctx)) {
+        Ok(decision) => decision == Decision::Allow,
+        Err(e) => {
+            eprintln!("{}", t!("msg.access_denied", e));
+            false
+        }
+    }
+}
+
+/// Check the credentials of a user, disabled accounts being refused
+pub fn authenticate(name: &str, password: &str) -> ServiceResult<User> {
+    let name = &normalize_name(name);
+    let user = User::get(name).unwrap_or_else(|| {
+        //No collision since input validation does not allow empty string as username
+        User::new("", "", Role::Reviewer)
+    });
+    let known = if user.name.is_empty() { None } else { Some(&*user.name) };
+
+    if !checked_password(known, &user.password, password) {
+        audit::record(name, AuditAction::FailedLogin, "");
+        return Err(ServiceError::Authentication);
+    }
+
+    if user.disabled {
+        audit::record(&user.name, AuditAction::FailedLogin, "disabled=true");
+        return Err(ServiceError::Denied(t!("error.account_disabled")));
+    }
+
+    audit::record(&user.name, AuditAction::Login, "");
+    Ok(user)
+}
+
+/// Create an account, only reviewers and owners may register themselves. Names which could be
+/// mistaken for another user or establishment are refused, to prevent impersonation.
+pub fn register(name: &str, password: &str, role: Role) -> ServiceResult<User> {
+    let name = &normalize_name(name);
+    validated(is_name_valid(name))?;
+    validated(is_username_acceptable(name))?;
+    validated(is_password_valid(name, password, PASS_DEFAULT_SCORE))?;
+    let role = match role {
+        Role::Reviewer => Role::Reviewer,
+        Role::Owner { owned_establishment } => {
+            let owned_establishment = normalize_name(&owned_establishment);
+            validated(is_name_valid(&owned_establishment))?;
+            check_not_confusable(&owned_establishment, Some(&owned_establishment))?;
+            Role::Owner { owned_establishment }
+        }
+        _ => return Err(ServiceError::Invalid(t!("error.register_role"))),
+    };
+    let owned_establishment = match &role {
+        Role::Owner { owned_establishment } => Some(owned_establishment.as_str()),
+        _ => None,
+    };
+    check_not_confusable(name, owned_establishment)?;
+
+    let user = User::new(name, &hash_password(password.as_bytes()), role);
+    user.save()?;
+    audit::record(&user.name, AuditAction::Register, "");
+
+    Ok(user)
+}
+
+/// Refuse a new name looking like the name of an existing user or establishment. Only `own`, the
+/// establishment an owner registers for, may be used as is; an identical user name is left to the
+/// database, which refuses it as already existing.
+fn check_not_confusable(name: &str, own: Option<&str>) -> ServiceResult<()> {
+    let (establishments, users) = {
+        let db = DATABASE.lock().unwrap();
+        let users: Vec<String> = db.get_users().into_iter().map(|user| user.name).collect();
+        (db.get_establishments(), users)
+    };
+
+    let skeleton = name_skeleton(name);
+    let mut others = establishments
+        .iter()
+        .filter(|establishment| Some(establishment.as_str()) != own)
+        .chain(users.iter().filter(|user| *user != name));
+    match others.find(|other| name_skeleton(other) == skeleton) {
+        Some(other) => Err(ServiceError::Invalid(t!("validation.confusable_name", other))),
+        None => Ok(()),
+    }
+}
+
+/// Add a review, possibly graded per criterion, its overall grade being derived from the scores when not given.
+/// Offensive words of the comment are masked, and reviews looking like spam are flagged
+/// for moderation instead of being published. A new establishment may not look like an existing one.
+pub fn add_review(
+    user: &User,
+    establishment: &str,
+    comment: &str,
+    grade: Option<u8>,
+    scores: Scores,
+) -> ServiceResult<Review> {
+    let establishment = &normalize_name(establishment);
+    validated(is_name_valid(establishment))?;
+    authorize(
+        user,
+        establishment,
+        "review",
+        &Context::new(user).with_establishment(establishment),
+        &t!("error.cannot_review"),
+    )?;
+    if !DATABASE.lock().unwrap().get_establishments().contains(establishment) {
+        check_not_confusable(establishment, None)?;
+    }
+    validated(is_text_length_valid(comment, REVIEW_MIN_SIZE, REVIEW_MAX_SIZE))?;
+    let comment = filter_comment(comment).map_err(ServiceError::Invalid)?;
+    let grade = overall_grade(grade, &scores)?;
+
+    let mut review = Review::new(establishment, &user.name, &comment, grade).with_scores(scores);
+    let suspicions = flag_if_spam(&mut review);
+    review.save()?;
+    audit::record(&user.name, AuditAction::AddReview, establishment);
+    audit_suspicions(&review, &suspicions);
+
+    Ok(review)
+}
+
+/// Change the comment and grades of the user's own review, which is flagged again if it now looks like spam
+pub fn edit_review(
+    user: &User,
+    establishment: &str,
+    comment: &str,
+    grade: Option<u8>,
+    scores: Scores,
+) -> ServiceResult<Review> {
+    let mut review = Review::get(&user.name, establishment).ok_or(ServiceError::NotFound(t!("error.missing_review")))?;
+    authorize(
+        user,
+        &user.name,
+        "edit",
+        &Context::new(user).with_review(&review),
+        &t!("error.cannot_edit"),
+    )?;
+    validated(is_text_length_valid(comment, REVIEW_MIN_SIZE, REVIEW_MAX_SIZE))?;
+    review.comment = filter_comment(comment).map_err(ServiceError::Invalid)?;
+    review.grade = overall_grade(grade, &scores)?;
+    review.scores = scores;
+
+    let suspicions = flag_if_spam(&mut review);
+    review.update()?;
+    audit::record(&user.name, AuditAction::EditReview, establishment);
+    audit_suspicions(&review, &suspicions);
+
+    Ok(review)
+}
+
+/// Flag a published review that looks like spam compared to the other reviews of its author
+fn flag_if_spam(review: &mut Review) -> Vec<spam::Suspicion> {
+    let previous: Vec<Review> = Review::by(&review.reviewer)
+        .into_iter()
+        .filter(|other| other.establishment != review.establishment)
+        .collect();
+    let suspicions = spam::check(review, &previous);
+    if !suspicions.is_empty() && review.status == ReviewStatus::Published {
+        review.status = ReviewStatus::Flagged;
+    }
+    suspicions
+}
+
+fn audit_suspicions(review: &Review, suspicions: &[spam::Suspicion]) {
+    if !suspicions.is_empty() {
+        audit::record(
+            &review.reviewer,
+            AuditAction::FlagReview,
+            &format!("{} : {:?}", review.establishment, suspicions),
+        );
+    }
+}
+
+/// Check the scores and the overall grade, derived from the scores when not given
+pub fn overall_grade(grade: Option<u8>, scores: &Scores) -> ServiceResult<u8> {
+    for score in scores.values() {
+        validated(is_number_in_range(score, REVIEW_MIN_GRADE, REVIEW_MAX_GRADE))?;
+    }
+    let grade = grade
+        .or_else(|| Review::derived_grade(scores))
+        .ok_or_else(|| ServiceError::Invalid(t!("error.missing_grade")))?;
+    validated(is_number_in_range(&grade, REVIEW_MIN_GRADE, REVIEW_MAX_GRADE))?;
+    Ok(grade)
+}
+
+pub fn own_reviews(user: &User) -> Vec<Review> {
+    Review::by(&user.name)
+}
+
+/// Reviews of an establishment that the user may read, hidden ones being
+/// left out unless the user moderates the establishment or wrote them
+pub fn establishment_reviews(user: &User, establishment: &str) -> Vec<Review> {
+    let ctx = Context::new(user).with_establishment(establishment);
+    let can_read_establishment = can_see(user, establishment, "read", &ctx);
+    let can_see_hidden = can_see(user, establishment, "hide", &ctx);
+
+    Review::of(establishment)
+        .into_iter()
+        .filter(|review| review.status == ReviewStatus::Published || can_see_hidden || review.reviewer == user.name)
+        .filter(|review| can_read_establishment || can_see(user, &review.reviewer, "read", &Context::new(user).with_review(review)))
+        .collect()
+}
+
+/// Reviews the user may read matching `query`, across all establishments
+pub fn search_reviews(user: &User, query: &ReviewQuery) -> Vec<Review> {
+    let (names, comments, tallies) = {
+        let db = DATABASE.lock().unwrap();
+        (db.get_establishments(), db.search_comments(&query.text), db.get_tallies())
+    };
+
+    // Only the establishments named like the text or with a matching comment need to be read
+    let reviews = names
+        .iter()
+        .filter(|name| search::contains(name, &query.text) || comments.iter().any(|key| &key.establishment == *name))
+        .flat_map(|name| establishment_reviews(user, name))
+        .collect();
+    query.apply(reviews, &comments, &tallies)
+}
+
+/// Sort reviews in the given order, helpful votes being counted for `SortOrder::Helpful`
+pub fn sort_reviews(reviews: &mut [Review], order: SortOrder) {
+    let tallies = DATABASE.lock().unwrap().get_tallies();
+    search::sort(reviews, order, &tallies);
+}
+
+/// Votes received by each review having any
+pub fn tallies() -> HashMap<ReviewKey, Tally> {
+    DATABASE.lock().unwrap().get_tallies()
+}
+
+/// Vote on whether a published review was helpful, replacing the user's previous
+/// vote on it, and return the votes it now has. The review is designated by its establishment
+/// and author and is not returned, since voting on it does not grant reading it.
+pub fn vote(user: &User, establishment: &str, reviewer: &str, helpful: bool) -> ServiceResult<Tally> {
+    let review = Review::get(reviewer, establishment)
+        .filter(|review| review.status == ReviewStatus::Published)
+        .ok_or(ServiceError::NotFound(t!("error.missing_review")))?;
+    authorize(
+        user,
+        establishment,
+        "vote",
+        &Context::new(user).with_review(&review),
+        &t!("error.cannot_vote"),
+    )?;
+
+    let key = ReviewKey::of(&review);
+    let tally = {
+        let mut db = DATABASE.lock().unwrap();
+        db.store_vote(Vote {
+            voter: user.name.clone(),
+            review: key.clone(),
+            helpful,
+        });
+        db.get_tallies().get(&key).copied().unwrap_or_default()
+    };
+    audit::record(
+        &user.name,
+        AuditAction::Vote,
+        &format!("{} / {} : helpful={}", establishment, reviewer, helpful),
+    );
+
+    Ok(tally)
+}
+
+/// Names of the establishments containing `text`, ignoring case and accents
+pub fn search_establishments(text: &str) -> Vec<String> {
+    let names = DATABASE.lock().unwrap().get_establishments();
+    names.into_iter().filter(|name| search::contains(name, text)).collect()
+}
+
+/// An establishment along with how many of its reviews the user may read
+#[derive(Serialize)]
+pub struct Establishment {
+    pub name: String,
+    pub reviews: usize,
+}
+
+pub fn establishments(user: &User) -> Vec<Establishment> {
+    let names = DATABASE.lock().unwrap().get_establishments();
+    names
+        .into_iter()
+        .map(|name| Establishment {
+            reviews: establishment_reviews(user, &name).len(),
+            name,
+        })
+        .collect()
+}
+
+/// Grades of an establishment, computed from the published reviews the user may read
+pub fn establishment_stats(user: &User, establishment: &str) -> ServiceResult<EstablishmentStats> {
+    authorize(
+        user,
+        establishment,
+        "stats",
+        &Context::new(user).with_establishment(establishment),
+        &t!("error.cannot_stats"),
+    )?;
+    Ok(published_stats(user, establishment))
+}
+
+/// Grades of every establishment whose statistics the user may see
+pub fn all_stats(user: &User) -> Vec<EstablishmentStats> {
+    let names = DATABASE.lock().unwrap().get_establishments();
+    names
+        .into_iter()
+        .filter(|name| can_see(user, name, "stats", &Context::new(user).with_establishment(name)))
+        .map(|name| published_stats(user, &name))
+        .collect()
+}
+
+fn published_stats(user: &User, establishment: &str) -> EstablishmentStats {
+    let reviews: Vec<Review> = establishment_reviews(user, establishment)
+        .into_iter()
+        .filter(|review| review.status == ReviewStatus::Published)
+        .collect();
+    EstablishmentStats::of(establishment, &reviews)
+}
+
+pub fn delete_review(user: &User, establishment: &str, reviewer: &str) -> ServiceResult<Review> {
+    authorize(
+        user,
+        establishment,
+        "delete",
+        &Context::new(user).with_establishment(establishment),
+        &t!("error.cannot_delete"),
+    )?;
+    let review = Review::get(reviewer, establishment).ok_or(ServiceError::NotFound(t!("error.missing_review")))?;
+
+    review.delete();
+    audit::record(
+        &user.name,
+        AuditAction::DeleteReview,
+        &format!("{} / {}", review.establishment, review.reviewer),
+    );
+
+    Ok(review)
+}
+
+/// Hide a published review, or publish again a hidden or flagged one, returns it with its new status
+pub fn toggle_review_visibility(user: &User, establishment: &str, reviewer: &str) -> ServiceResult<Review> {
+    authorize(
+        user,
+        establishment,
+        "hide",
+        &Context::new(user).with_establishment(establishment),
+        &t!("error.cannot_moderate"),
+    )?;
+    let mut review = Review::get(reviewer, establishment).ok_or(ServiceError::NotFound(t!("error.missing_review")))?;
+
+    let status = match review.status {
+        ReviewStatus::Published => ReviewStatus::Hidden,
+        ReviewStatus::Hidden | ReviewStatus::Flagged => ReviewStatus::Published,
+    };
+    review.set_status(status);
+    audit::record(
+        &user.name,
+        AuditAction::HideReview,
+        &format!("{} / {} : {:?}", review.establishment, review.reviewer, status),
+    );
+
+    Ok(review)
+}
+
+pub fn users(user: &User) -> ServiceResult<Vec<User>> {
+    authorize(user, "users", "manage", &Context::new(user), &t!("error.not_admin"))?;
+    Ok(DATABASE.lock().unwrap().get_users())
+}
+
+/// Put an establishment in a category, or take it out of its category with `None`
+pub fn set_category(user: &User, establishment: &str, category: Option<&str>) -> ServiceResult<()> {
+    authorize(user, "categories", "manage", &Context::new(user), &t!("error.not_admin"))?;
+    let establishment = normalize_name(establishment);
+    let category = category.map(normalize_name);
+    validated(is_name_valid(&establishment))?;
+    if let Some(category) = &category {
+        validated(is_name_valid(category))?;
+    }
+
+    DATABASE.lock().unwrap().set_category(&establishment, category.as_deref());
+    audit::record(
+        &user.name,
+        AuditAction::Categorize,
+        &format!("{} : {}", establishment, category.as_deref().unwrap_or("-")),
+    );
+
+    Ok(())
+}
+
+pub fn change_role(user: &User, name: &str, role: Role) -> ServiceResult<User> {
+    authorize(user, "users", "manage", &Context::new(user), &t!("error.not_admin"))?;
+    let mut target = User::get(name).ok_or(ServiceError::NotFound(t!("error.unknown_user")))?;
+
+    target.role = role;
+    target.update()?;
+    audit::record(
+        &user.name,
+        AuditAction::ChangeRole,
+        &format!("{} : {:?}", target.name, target.role),
+    );
+
+    Ok(target)
+}
+
+/// Replace the access policy by the one of a CSV file, refused when any regression case fails,
+/// the failing cases being returned then
+pub fn import_policy(user: &User, policy: &str, cases: Vec<PolicyCase>) -> ServiceResult<Vec<CaseFailure>> {
+    authorize(user, "policy", "manage", &Context::new(user), &t!("error.not_admin"))?;
+
+    let failures = block_on(authorization::import_policy(policy, cases)).map_err(|e| ServiceError::Failed(e.into()))?;
+    if failures.is_empty() {
+        audit::record(&user.name, AuditAction::ImportPolicy, policy);
+    }
+    Ok(failures)
+}
+
+/// Disable an account, or enable it again if it was disabled. Users cannot disable their own account.
+pub fn toggle_account(user: &User, name: &str) -> ServiceResult<User> {
+    authorize(user, "users", "manage", &Context::new(user), &t!("error.not_admin"))?;
+    if name == user.name {
+        return Err(ServiceError::Invalid(t!("error.disable_self")));
+    }
+    let mut target = User::get(name).ok_or(ServiceError::NotFound(t!("error.unknown_user")))?;
+
+    target.disabled = !target.disabled;
+    target.update()?;
+    audit::record(
+        &user.name,
+        AuditAction::ToggleAccount,
+        &format!("{} : disabled={}", target.name, target.disabled),
+    );
+
+    Ok(target)
+}
+
+/// Open a dispute on the reviews of an establishment, or close it, returns whether one is now open
+pub fn toggle_dispute(user: &User, establishment: &str) -> ServiceResult<bool> {
+    authorize(user, "disputes", "manage", &Context::new(user), &t!("error.not_admin"))?;
+    let establishment = normalize_name(establishment);
+    validated(is_name_valid(&establishment))?;
+
+    let disputed = {
+        let mut db = DATABASE.lock().unwrap();
+        let disputed = !db.is_disputed(&establishment);
+        db.set_disputed(&establishment, disputed);
+        disputed
+    };
+    audit::record(
+        &user.name,
+        AuditAction::Dispute,
+        &format!("{} : disputed={}", establishment, disputed),
+    );
+
+    Ok(disputed)
+}
+
+/// Sessions still open, in every process
+pub fn sessions(user: &User) -> ServiceResult<Vec<Session>> {
+    authorize(user, "sessions", "manage", &Context::new(user), &t!("error.not_admin"))?;
+    Ok(Session::active()?)
+}
+
+/// Revoke a session by its public id, which ends it at the next action of its user
+pub fn revoke_session(user: &User, id: &str) -> ServiceResult<Session> {
+    authorize(user, "sessions", "manage", &Context::new(user), &t!("error.not_admin"))?;
+    let session = Session::revoke(id)?.ok_or(ServiceError::NotFound(t!("error.unknown_session")))?;
+    audit::record(&user.name, AuditAction::RevokeSession, &format!("{} ({})", session.id, session.username));
+    Ok(session)
+}
+
+/// Entries of the audit log matching `filter`, along with the index of the entry from which
+/// the log was altered if it was, for the entries to be shown with a warning
+pub fn audit_entries(user: &User, filter: &AuditFilter) -> ServiceResult<(Vec<AuditEntry>, Option<usize>)> {
+    authorize(user, "audit", "manage", &Context::new(user), &t!("error.not_admin"))?;
+
+    let mut log = AUDIT.lock().unwrap();
+    log.refresh();
+    Ok((log.entries(filter), log.verify().err()))
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Criterion;
+
+    #[test]
+    fn test_add_review_checks_input_and_authorization() {
+        let reviewer = User::new("service reviewer", "", Role::Reviewer);
+        let owner = User::new(
+            "service owner",
+            "",
+            Role::Owner {
+                owned_establishment: "Service Bistrot".to_string(),
+            },
+        );
+
+        assert!(matches!(add_review(&reviewer, "Service Bistrot", "", Some(3), Scores::new()), Err(ServiceError::Invalid(..))));
+        assert!(matches!(add_review(&reviewer, "Service Bistrot", "Bon", Some(6), Scores::new()), Err(ServiceError::Invalid(..))));
+        assert!(matches!(add_review(&reviewer, "Service Bistrot", "Serveur connard", Some(1), Scores::new()), Err(ServiceError::Invalid(..))));
+        assert!(matches!(add_review(&owner, "Service Bistrot", "Venez !", Some(5), Scores::new()), Err(ServiceError::Denied(..))));
+
+        add_review(&reviewer, "Service Bistrot", "Bon", Some(4), Scores::new()).unwrap();
+        assert!(matches!(add_review(&reviewer, "Service Bistrot", "Bon", Some(4), Scores::new()), Err(ServiceError::Failed(..))));
+        assert!(matches!(add_review(&reviewer, "SERVICE Bistr0t", "Bon", Some(4), Scores::new()), Err(ServiceError::Invalid(..))));
+        assert_eq!(establishment_reviews(&owner, "Service Bistrot").len(), 1);
+        assert!(matches!(
+            delete_review(&reviewer, "Service Bistrot", "service reviewer"),
+            Err(ServiceError::Denied(..))
+        ));
+    }
+
+    #[test]
+    fn test_toggles_require_authorization() {
+        let admin = User::new("toggle admin", "", Role::Admin);
+        let reviewer = User::new("toggle reviewer", "", Role::Reviewer);
+        DATABASE.lock().unwrap().store_user(&reviewer).unwrap();
+        Review::new("Toggle Bistrot", "toggle reviewer", "Bon", 4).save().unwrap();
+
+        assert!(matches!(toggle_review_visibility(&reviewer, "Toggle Bistrot", "toggle reviewer"), Err(ServiceError::Denied(..))));
+        assert_eq!(toggle_review_visibility(&admin, "Toggle Bistrot", "toggle reviewer").unwrap().status, ReviewStatus::Hidden);
+        assert_eq!(toggle_review_visibility(&admin, "Toggle Bistrot", "toggle reviewer").unwrap().status, ReviewStatus::Published);
+        assert!(matches!(toggle_review_visibility(&admin, "Toggle Bistrot", "nobody"), Err(ServiceError::NotFound(..))));
+
+        assert!(matches!(toggle_account(&reviewer, "toggle admin"), Err(ServiceError::Denied(..))));
+        assert!(matches!(toggle_account(&admin, "toggle admin"), Err(ServiceError::Invalid(..))));
+        assert!(toggle_account(&admin, "toggle reviewer").unwrap().disabled);
+        assert!(!toggle_account(&admin, "toggle reviewer").unwrap().disabled);
+
+        assert!(matches!(toggle_dispute(&reviewer, "Toggle Bistrot"), Err(ServiceError::Denied(..))));
+        assert!(toggle_dispute(&admin, "Toggle Bistrot").unwrap());
+        assert!(DATABASE.lock().unwrap().is_disputed("Toggle Bistrot"));
+        assert!(!toggle_dispute(&admin, "Toggle Bistrot").unwrap());
+    }
+
+    #[test]
+    fn test_scored_review() {
+        let reviewer = User::new("scores reviewer", "", Role::Reviewer);
+        let scores = Scores::from([(Criterion::Food, 5), (Criterion::Service, 2)]);
+
+        let review = add_review(&reviewer, "Scores Bistrot", "Bon", None, scores.clone()).unwrap();
+        assert_eq!(review.grade, 4);
+        assert_eq!(review.scores, scores);
+
+        let invalid = Scores::from([(Criterion::Price, 0)]);
+        assert!(matches!(
+            add_review(&reviewer, "Scores Brasserie", "Bon", Some(3), invalid),
+            Err(ServiceError::Invalid(..))
+        ));
+        assert!(matches!(
+            add_review(&reviewer, "Scores Brasserie", "Bon", None, Scores::new()),
+            Err(ServiceError::Invalid(..))
+        ));
+        assert_eq!(overall_grade(Some(1), &scores).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_establishment_stats_visibility() {
+        let reviewer = User::new("stats reviewer", "", Role::Reviewer);
+        let owner = User::new(
+            "stats owner",
+            "",
+            Role::Owner {
+                owned_establishment: "Stats Service Bistrot".to_string(),
+            },
+        );
+        let admin = User::new("stats admin", "", Role::Admin);
+        add_review(&reviewer, "Stats Service Bistrot", "Bon", Some(4), Scores::new()).unwrap();
+
+        assert_eq!(establishment_stats(&owner, "Stats Service Bistrot").unwrap().mean, Some(4.0));
+        assert_eq!(establishment_stats(&admin, "Stats Service Bistrot").unwrap().count, 1);
+        assert!(matches!(establishment_stats(&owner, "Other Stats Bistrot"), Err(ServiceError::Denied(..))));
+        assert!(matches!(
+            establishment_stats(&reviewer, "Stats Service Bistrot"),
+            Err(ServiceError::Denied(..))
+        ));
+        assert!(all_stats(&owner).iter().all(|stats| stats.establishment == "Stats Service Bistrot"));
+    }
+
+    #[test]
+    fn test_search_respects_read_rules() {
+        let reviewer = User::new("search reviewer", "", Role::Reviewer);
+        add_review(&reviewer, "Recherche Brasserie", "Très bon café", Some(5), Scores::new()).unwrap();
+
+        let query = ReviewQuery {
+            text: "CAFE".to_string(),
+            ..Default::default()
+        };
+        let found = search_reviews(&reviewer, &query);
+        assert!(!found.is_empty());
+        assert!(found.iter().all(|review| review.reviewer == reviewer.name));
+        assert_eq!(search_establishments("brasserie"), vec!["Recherche Brasserie".to_string()]);
+    }
+
+    #[test]
+    fn test_bursts_are_flagged() {
+        let reviewer = User::new("spam reviewer", "", Role::Reviewer);
+        let moderator = User::new(
+            "spam moderator",
+            "",
+            Role::Moderator {
+                establishments: vec!["Spam Bistrot 3".to_string()],
+                categories: Vec::new(),
+            },
+        );
+
+        for i in 1..3 {
+            let review = add_review(&reviewer, &format!("Spam Bistrot {}", i), "Nul", Some(1), Scores::new()).unwrap();
+            assert_eq!(review.status, ReviewStatus::Published);
+        }
+        let review = add_review(&reviewer, "Spam Bistrot 3", "À fuir", Some(1), Scores::new()).unwrap();
+        assert_eq!(review.status, ReviewStatus::Flagged);
+
+        assert_eq!(establishment_reviews(&moderator, "Spam Bistrot 3").len(), 1);
+        assert_eq!(published_stats(&moderator, "Spam Bistrot 3").count, 0);
+
+        // Editing a review into a copy of another one flags it too
+        let comment = "Service lent et plats froids, à éviter";
+        add_review(&reviewer, "Spam Bistrot 4", comment, Some(2), Scores::new()).unwrap();
+        let review = add_review(&reviewer, "Spam Bistrot 5", "Accueil chaleureux", Some(4), Scores::new()).unwrap();
+        assert_eq!(review.status, ReviewStatus::Published);
+        let review = edit_review(&reviewer, "Spam Bistrot 5", comment, Some(2), Scores::new()).unwrap();
+        assert_eq!(review.status, ReviewStatus::Flagged);
+    }
+
+    #[test]
+    fn test_vote() {
+        let author = User::new("vote author", "", Role::Reviewer);
+        let reviewer = User::new("vote reviewer", "", Role::Reviewer);
+        let moderator = User::new(
+            "vote moderator",
+            "",
+            Role::Moderator {
+                establishments: vec!["Vote Bistrot".to_string()],
+                categories: Vec::new(),
+            },
+        );
+        let owner = User::new(
+            "vote owner",
+            "",
+            Role::Owner {
+                owned_establishment: "Vote Bistrot".to_string(),
+            },
+        );
+        add_review(&author, "Vote Bistrot", "Bon", Some(4), Scores::new()).unwrap();
+
+        assert!(matches!(vote(&author, "Vote Bistrot", "vote author", true), Err(ServiceError::Denied(..))));
+        assert!(matches!(vote(&owner, "Vote Bistrot", "vote author", true), Err(ServiceError::Denied(..))));
+        assert!(matches!(vote(&reviewer, "Vote Bistrot", "nobody", true), Err(ServiceError::NotFound(..))));
+
+        assert_eq!(vote(&reviewer, "Vote Bistrot", "vote author", true).unwrap(), Tally { helpful: 1, unhelpful: 0 });
+        assert_eq!(vote(&reviewer, "Vote Bistrot", "vote author", false).unwrap(), Tally { helpful: 0, unhelpful: 1 });
+        assert_eq!(vote(&moderator, "Vote Bistrot", "vote author", true).unwrap(), Tally { helpful: 1, unhelpful: 1 });
+
+        // Hidden reviews cannot be voted on
+        DATABASE.lock().unwrap().set_review_status("vote author", "Vote Bistrot", ReviewStatus::Hidden);
+        assert!(matches!(vote(&reviewer, "Vote Bistrot", "vote author", true), Err(ServiceError::NotFound(..))));
+    }
+
+    #[test]
+    fn test_register_and_authenticate() {
+        let password = "Wn3!qTz8#pLk2vRs";
+        let owner = User::new(
+            "Toto",
+            "",
+            Role::Owner {
+                owned_establishment: "McDonalds".to_string(),
+            },
+        );
+        DATABASE.lock().unwrap().store_user(&owner).unwrap();
+
+        assert!(matches!(register("service user", "1234", Role::Reviewer), Err(ServiceError::Invalid(..))));
+        assert!(matches!(register("service user", password, Role::Admin), Err(ServiceError::Invalid(..))));
+        assert!(matches!(register("Sh1t Lover", password, Role::Reviewer), Err(ServiceError::Invalid(..))));
+        assert!(matches!(register("T0T0", password, Role::Reviewer), Err(ServiceError::Invalid(..))));
+        assert!(matches!(register("rncDonalds", password, Role::Reviewer), Err(ServiceError::Invalid(..))));
+        assert!(matches!(register("McDonalds", password, Role::Reviewer), Err(ServiceError::Invalid(..))));
+        assert!(matches!(
+            register("Mc Owner", password, Role::Owner { owned_establishment: "McD0nalds".to_string() }),
+            Err(ServiceError::Invalid(..))
+        ));
+        register("Chez Max", password, Role::Owner { owned_establishment: "Chez Max".to_string() }).unwrap();
+        register("service user", password, Role::Reviewer).unwrap();
+
+        assert!(authenticate("service user", password).is_ok());
+        register("Service Usagère", password, Role::Reviewer).unwrap();
+        assert!(authenticate("Service Usage\u{300}re", password).is_ok());
+        assert!(matches!(authenticate("service user", "wrong"), Err(ServiceError::Authentication)));
+        assert!(matches!(authenticate("service nobody", password), Err(ServiceError::Authentication)));
+    }
+}
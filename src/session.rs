@@ -0,0 +1,280 @@
+use crate::User;
+use crate::i18n::t;
+use anyhow::{bail, Context};
+use chrono::{DateTime, Duration, Utc};
+use derive_more::Display;
+use once_cell::sync::Lazy;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    env,
+    fs::OpenOptions,
+    io::{Read, Seek, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+pub const DEFAULT_IDLE_TIMEOUT_SECS: i64 = 15 * 60;
+static IDLE_TIMEOUT_ENV: &str = "SLH_SESSION_IDLE_TIMEOUT";
+/// Shared by the interactive processes and the API server, so that any of them sees revocations
+static SESSIONS_FILE: &str = "sessions.json";
+const ID_BYTES: usize = 8;
+
+static SESSIONS: Lazy<SessionStore> = Lazy::new(|| {
+    // Tests must not see nor revoke the real sessions
+    if cfg!(test) {
+        SessionStore::in_memory()
+    } else {
+        SessionStore::open(SESSIONS_FILE)
+    }
+});
+
+/// Open sessions by id, read again from their file at each access
+struct SessionStore {
+    path: Option<PathBuf>,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl SessionStore {
+    fn open(path: &str) -> Self {
+        Self {
+            path: Some(PathBuf::from(path)),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn in_memory() -> Self {
+        Self {
+            path: None,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Apply `change` to the up-to-date sessions and write them back. The file stays locked
+    /// meanwhile, so that processes do not undo each other's changes.
+    fn update<T>(&self, change: impl FnOnce(&mut HashMap<String, Session>) -> T) -> anyhow::Result<T> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(path) = &self.path else {
+            return Ok(change(&mut sessions));
+        };
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| t!("error.sessions_file"))?;
+        file.lock().with_context(|| t!("error.sessions_file"))?;
+
+        let mut content = String::new();
+        file.read_to_string(&mut content).with_context(|| t!("error.sessions_file"))?;
+        *sessions = match content.trim() {
+            "" => HashMap::new(),
+            content => serde_json::from_str(content).with_context(|| t!("error.sessions_file"))?,
+        };
+
+        let result = change(&mut sessions);
+        file.set_len(0)?;
+        file.rewind()?;
+        file.write_all(serde_json::to_string(&*sessions)?.as_bytes())
+            .with_context(|| t!("error.sessions_file"))?;
+        Ok(result)
+    }
+}
+
+/// A login to the interactive menu or to the API. Its secret value is `<id>_<secret>`, held by
+/// the client only, the public id being the one under which admins see and revoke the session.
+#[derive(Debug, Clone, Serialize, Deserialize, Display)]
+#[display(
+    fmt = "{}",
+    "t!(
+        \"session.display\",
+        id,
+        username,
+        created_at.format(\"%d.%m.%Y %H:%M\"),
+        last_activity.format(\"%d.%m.%Y %H:%M\")
+    )"
+)]
+pub struct Session {
+    pub id: String,
+    hash: String,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+    pub last_activity: DateTime<Utc>,
+    idle_timeout_secs: i64,
+}
+
+impl Session {
+    /// Open a new session for an authenticated user, returned along with its secret value
+    pub fn open(user: &User) -> anyhow::Result<(Self, String)> {
+        Self::open_with_timeout(user, idle_timeout())
+    }
+
+    fn open_with_timeout(user: &User, idle_timeout: Duration) -> anyhow::Result<(Self, String)> {
+        let id = random_hex(ID_BYTES);
+        let secret = format!("{}_{}", id, random_hex(32));
+        let now = Utc::now();
+        let session = Self {
+            id,
+            hash: hash_secret(&secret),
+            username: user.name.clone(),
+            created_at: now,
+            last_activity: now,
+            idle_timeout_secs: idle_timeout.num_seconds(),
+        };
+
+        SESSIONS.update(|sessions| sessions.insert(session.id.clone(), session.clone()))?;
+        Ok((session, secret))
+    }
+
+    /// Find an open session by its secret value
+    pub fn find(secret: &str) -> anyhow::Result<Option<Self>> {
+        let (id, _) = secret.split_once('_').unwrap_or_default();
+        let session = SESSIONS.update(|sessions| sessions.get(id).cloned())?;
+        Ok(session.filter(|session| session.hash == hash_secret(secret)))
+    }
+
+    /// Get the up-to-date user behind this session and refresh its activity time.
+    /// Fails if the session was revoked, has expired or if the account was disabled or no longer exists.
+    pub fn user(&self) -> anyhow::Result<User> {
+        self.touch(Utc::now())?;
+
+        match User::get(&self.username) {
+            Some(user) if user.disabled => {
+                self.close();
+                bail!(t!("error.account_was_disabled"))
+            }
+            Some(user) => Ok(user),
+            None => {
+                self.close();
+                bail!(t!("error.account_gone"))
+            }
+        }
+    }
+
+    fn touch(&self, now: DateTime<Utc>) -> anyhow::Result<()> {
+        SESSIONS.update(|sessions| {
+            let Some(session) = sessions.get_mut(&self.id) else {
+                bail!(t!("error.session_revoked"))
+            };
+
+            if session.is_expired_at(now) {
+                sessions.remove(&self.id);
+                bail!(t!("error.session_expired"))
+            }
+
+            session.last_activity = now;
+            Ok(())
+        })?
+    }
+
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        now - self.last_activity > Duration::seconds(self.idle_timeout_secs)
+    }
+
+    pub fn close(&self) {
+        if let Err(e) = Self::revoke(&self.id) {
+            eprintln!("{:#}", e);
+        }
+    }
+
+    /// Get all sessions that are still open, expired ones are dropped
+    pub fn active() -> anyhow::Result<Vec<Self>> {
+        let now = Utc::now();
+        let mut active: Vec<Self> = SESSIONS.update(|sessions| {
+            sessions.retain(|_, session| !session.is_expired_at(now));
+            sessions.values().cloned().collect()
+        })?;
+
+        active.sort_by_key(|session| session.created_at);
+        Ok(active)
+    }
+
+    /// Revoke a session by its public id, returns the session if it existed
+    pub fn revoke(id: &str) -> anyhow::Result<Option<Self>> {
+        SESSIONS.update(|sessions| sessions.remove(id))
+    }
+}
+
+fn idle_timeout() -> Duration {
+    let secs = env::var(IDLE_TIMEOUT_ENV)
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS);
+    Duration::seconds(secs)
+}
+
+fn random_hex(len: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| format!("{:02x}", rng.gen::<u8>())).collect()
+}
+
+fn hash_secret(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Role;
+
+    #[test]
+    fn test_session_expires_after_idle_timeout() {
+        let user = User::new("idle", "hash", Role::Reviewer);
+        let (session, _) = Session::open_with_timeout(&user, Duration::seconds(60)).unwrap();
+        let now = session.last_activity;
+
+        assert!(!session.is_expired_at(now + Duration::seconds(60)));
+        assert!(session.is_expired_at(now + Duration::seconds(61)));
+        assert!(session.touch(now + Duration::seconds(30)).is_ok());
+        assert!(session.touch(now + Duration::seconds(120)).is_err());
+        assert!(Session::revoke(&session.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_revoked_session_is_rejected() {
+        let user = User::new("revoked", "hash", Role::Reviewer);
+        let (session, secret) = Session::open(&user).unwrap();
+
+        assert_eq!(Session::find(&secret).unwrap().unwrap().id, session.id);
+        assert!(Session::find(&session.id).unwrap().is_none());
+        assert!(Session::active().unwrap().iter().any(|s| s.id == session.id));
+        assert!(Session::revoke(&session.id).unwrap().is_some());
+        assert!(session.touch(Utc::now()).is_err());
+        assert!(Session::find(&secret).unwrap().is_none());
+        assert!(!Session::active().unwrap().iter().any(|s| s.id == session.id));
+    }
+
+    #[test]
+    fn test_deleted_account_closes_session() {
+        let user = User::new("not in database", "hash", Role::Reviewer);
+        let (session, _) = Session::open(&user).unwrap();
+
+        assert!(session.user().is_err());
+        assert!(Session::revoke(&session.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_processes_share_sessions() {
+        let path = std::env::temp_dir().join(format!("sessions-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let first = SessionStore::open(path.to_str().unwrap());
+        let second = SessionStore::open(path.to_str().unwrap());
+
+        let user = User::new("shared", "hash", Role::Reviewer);
+        let (session, _) = Session::open(&user).unwrap();
+        first.update(|sessions| sessions.insert(session.id.clone(), session.clone())).unwrap();
+        assert!(second.update(|sessions| sessions.remove(&session.id)).unwrap().is_some());
+        assert!(first.update(|sessions| sessions.get(&session.id).cloned()).unwrap().is_none());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::index::terms;
+use crate::utils::input_validation::REVIEW_MIN_GRADE;
+use crate::Review;
+use chrono::Duration;
+
+/// Share of common terms from which two comments are considered near-identical
+const SIMILARITY_THRESHOLD: f64 = 0.8;
+/// Comments with fewer terms are too common ("Très bon !") to be compared
+const SIMILARITY_MIN_TERMS: usize = 3;
+/// Number of reviews an author may post within `BURST_WINDOW` before the next ones are flagged
+const BURST_MAX: usize = 5;
+const BURST_WINDOW: Duration = Duration::hours(1);
+/// Number of reviews with the lowest grade within `GRADE_WINDOW` from which they are flagged. Only the
+/// lowest grade counts, a string of excellent visits being common enough.
+const GRADE_BURST: usize = 3;
+const GRADE_WINDOW: Duration = Duration::days(1);
+
+/// Why a new review looks like spam
+#[derive(Debug, PartialEq)]
+pub enum Suspicion {
+    /// The comment is nearly the same as the one of a previous review of the author
+    Duplicate(String),
+
+    /// The author posted too many reviews in a short time
+    Burst(usize),
+
+    /// The author keeps giving the lowest grade
+    GradeBurst(usize),
+}
+
+/// Check a new review against the previous reviews of its author
+pub fn check(review: &Review, previous: &[Review]) -> Vec<Suspicion> {
+    let mut suspicions = Vec::new();
+
+    let review_terms = terms(&review.comment);
+    if review_terms.len() >= SIMILARITY_MIN_TERMS {
+        let duplicate = previous.iter().find(|other| {
+            let other_terms = terms(&other.comment);
+            let common = review_terms.intersection(&other_terms).count();
+            let all = review_terms.union(&other_terms).count();
+            common as f64 / all as f64 >= SIMILARITY_THRESHOLD
+        });
+        if let Some(other) = duplicate {
+            suspicions.push(Suspicion::Duplicate(other.establishment.clone()));
+        }
+    }
+
+    let recent = |window: Duration| previous.iter().filter(move |other| review.created_at - other.created_at < window);
+
+    let count = recent(BURST_WINDOW).count() + 1;
+    if count > BURST_MAX {
+        suspicions.push(Suspicion::Burst(count));
+    }
+
+    if review.grade == REVIEW_MIN_GRADE {
+        let count = recent(GRADE_WINDOW).filter(|other| other.grade == review.grade).count() + 1;
+        if count >= GRADE_BURST {
+            suspicions.push(Suspicion::GradeBurst(count));
+        }
+    }
+
+    suspicions
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn review(establishment: &str, comment: &str, grade: u8, minutes_ago: i64) -> Review {
+        let mut review = Review::new(establishment, "spammer", comment, grade);
+        review.created_at = Utc::now() - Duration::minutes(minutes_ago);
+        review
+    }
+
+    #[test]
+    fn test_duplicate_comments() {
+        let previous = [review("Bistrot A", "Service lent et plats froids, à éviter", 2, 600)];
+
+        assert_eq!(
+            check(&review("Bistrot B", "Service lent, plats froids : à éviter !", 2, 0), &previous),
+            vec![Suspicion::Duplicate("Bistrot A".to_string())]
+        );
+        assert!(check(&review("Bistrot B", "Accueil chaleureux et cuisine soignée", 4, 0), &previous).is_empty());
+        assert!(check(&review("Bistrot B", "Bon", 3, 0), &[review("Bistrot A", "Bon", 3, 600)]).is_empty());
+    }
+
+    #[test]
+    fn test_bursts() {
+        let comments = ["Très bon", "Correct", "Pas mal", "Sympa", "Bien"];
+        let previous: Vec<Review> = comments
+            .iter()
+            .enumerate()
+            .map(|(i, comment)| review(&format!("Bistrot {}", i), comment, 3, i as i64 * 5))
+            .collect();
+        assert_eq!(check(&review("Bistrot Z", "Bof", 3, 0), &previous), vec![Suspicion::Burst(6)]);
+        assert!(check(&review("Bistrot Z", "Bof", 3, -120), &previous).is_empty());
+
+        let previous = [review("Bistrot A", "Nul", 1, 300), review("Bistrot B", "Horrible", 1, 200)];
+        assert_eq!(check(&review("Bistrot C", "À fuir", 1, 0), &previous), vec![Suspicion::GradeBurst(3)]);
+        assert!(check(&review("Bistrot C", "Moyen", 2, 0), &previous).is_empty());
+        assert!(check(&review("Bistrot C", "À fuir", 1, -2000), &previous).is_empty());
+
+        let previous = [review("Bistrot A", "Excellent", 5, 300), review("Bistrot B", "Parfait", 5, 200)];
+        assert!(check(&review("Bistrot C", "Superbe", 5, 0), &previous).is_empty());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::utils::input_validation::{REVIEW_MAX_GRADE, REVIEW_MIN_GRADE};
+use crate::{Criterion, Review};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use strum::IntoEnumIterator;
+
+/// Aggregated grades of an establishment
+#[derive(Debug, Serialize)]
+pub struct EstablishmentStats {
+    pub establishment: String,
+    pub count: usize,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    /// Number of reviews for each grade, from the lowest to the highest
+    pub distribution: Vec<usize>,
+    /// Mean grade of each month with reviews, oldest first
+    pub trend: Vec<Period>,
+    /// Mean score of each criterion graded by at least one review
+    pub criteria: Vec<CriterionStats>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct CriterionStats {
+    pub criterion: Criterion,
+    pub count: usize,
+    pub mean: f64,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Period {
+    /// Month as `YYYY-MM`
+    pub month: String,
+    pub count: usize,
+    pub mean: f64,
+}
+
+impl EstablishmentStats {
+    pub fn of(establishment: &str, reviews: &[Review]) -> Self {
+        let mut grades: Vec<u8> = reviews.iter().map(|review| review.grade).collect();
+        grades.sort_unstable();
+
+        let mut distribution = vec![0; (REVIEW_MAX_GRADE - REVIEW_MIN_GRADE + 1) as usize];
+        for grade in &grades {
+            if let Some(count) = distribution.get_mut(grade.saturating_sub(REVIEW_MIN_GRADE) as usize) {
+                *count += 1;
+            }
+        }
+
+        let mut months: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+        // Reviews stored before their date was recorded are left out of the trend
+        for review in reviews.iter().filter(|review| review.created_at != DateTime::<Utc>::default()) {
+            months
+                .entry(review.created_at.format("%Y-%m").to_string())
+                .or_default()
+                .push(review.grade);
+        }
+        let trend = months
+            .into_iter()
+            .map(|(month, grades)| Period {
+                month,
+                count: grades.len(),
+                mean: mean(&grades).unwrap_or_default(),
+            })
+            .collect();
+
+        let criteria = Criterion::iter()
+            .filter_map(|criterion| {
+                let scores: Vec<u8> = reviews
+                    .iter()
+                    .filter_map(|review| review.scores.get(&criterion).copied())
+                    .collect();
+                Some(CriterionStats {
+                    criterion,
+                    count: scores.len(),
+                    mean: mean(&scores)?,
+                })
+            })
+            .collect();
+
+        Self {
+            establishment: establishment.to_string(),
+            count: grades.len(),
+            mean: mean(&grades),
+            median: median(&grades),
+            distribution,
+            trend,
+            criteria,
+        }
+    }
+}
+
+fn mean(grades: &[u8]) -> Option<f64> {
+    if grades.is_empty() {
+        return None;
+    }
+    Some(grades.iter().map(|&grade| grade as f64).sum::<f64>() / grades.len() as f64)
+}
+
+/// Median of grades sorted in ascending order
+fn median(sorted: &[u8]) -> Option<f64> {
+    let middle = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 1 => Some(sorted[middle] as f64),
+        _ => Some((sorted[middle - 1] as f64 + sorted[middle] as f64) / 2.0),
+    }
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scores;
+    use chrono::TimeZone;
+
+    fn review(reviewer: &str, grade: u8, month: u32) -> Review {
+        let mut review = Review::new("Stats Bistrot", reviewer, "Bon", grade);
+        review.created_at = Utc.with_ymd_and_hms(2026, month, 15, 12, 0, 0).unwrap();
+        review
+    }
+
+    #[test]
+    fn test_stats_of_reviews() {
+        let mut undated = review("e", 3, 10);
+        undated.created_at = DateTime::default();
+        let food = |grade| Scores::from([(Criterion::Food, grade)]);
+        let reviews = vec![review("a", 5, 9).with_scores(food(4)), review("b", 2, 9).with_scores(food(2)), review("c", 4, 10), review("d", 5, 10), undated];
+        let stats = EstablishmentStats::of("Stats Bistrot", &reviews);
+
+        assert_eq!(stats.count, 5);
+        assert_eq!(stats.mean, Some(3.8));
+        assert_eq!(stats.median, Some(4.0));
+        assert_eq!(stats.distribution, vec![0, 1, 1, 1, 2]);
+        assert_eq!(
+            stats.criteria,
+            vec![CriterionStats { criterion: Criterion::Food, count: 2, mean: 3.0 }]
+        );
+        assert_eq!(
+            stats.trend,
+            vec![
+                Period { month: "2026-09".to_string(), count: 2, mean: 3.5 },
+                Period { month: "2026-10".to_string(), count: 2, mean: 4.5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stats_without_reviews() {
+        let stats = EstablishmentStats::of("Stats Bistrot", &[]);
+
+        assert_eq!(stats.count, 0);
+        assert_eq!(stats.mean, None);
+        assert_eq!(stats.median, None);
+        assert_eq!(stats.distribution, vec![0; 5]);
+        assert!(stats.trend.is_empty());
+        assert!(stats.criteria.is_empty());
+        assert_eq!(median(&[1, 3, 4]), Some(3.0));
+    }
+}
@@ -0,0 +1,219 @@
+use crate::audit::{self, AuditAction};
+use crate::db::DATABASE;
+use crate::i18n::t;
+use crate::User;
+use anyhow::bail;
+use chrono::{DateTime, Duration, Utc};
+use derive_more::Display;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use strum::EnumIter;
+
+static TOKEN_PREFIX: &str = "slh_";
+// Bytes of the public id of a token, enough for ids to never collide, since tokens are looked up by id
+const ID_BYTES: usize = 16;
+pub const MAX_VALIDITY_DAYS: i64 = 365;
+
+/// What a token may be used for, each scope granting some casbin actions
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Display, EnumIter)]
+pub enum Scope {
+    #[display(fmt = "read")]
+    Read,
+
+    #[display(fmt = "review")]
+    Review,
+
+    #[display(fmt = "vote")]
+    Vote,
+
+    #[display(fmt = "delete")]
+    Delete,
+
+    #[display(fmt = "manage")]
+    Manage,
+}
+
+impl Scope {
+    /// Whether the scope grants the casbin action `act`. Reading covers the statistics
+    /// of what can be read, every other scope grants the action of the same name.
+    pub fn allows(&self, act: &str) -> bool {
+        match self {
+            Scope::Read => matches!(act, "read" | "stats"),
+            Scope::Review => act == "review",
+            Scope::Vote => act == "vote",
+            Scope::Delete => act == "delete",
+            Scope::Manage => act == "manage",
+        }
+    }
+}
+
+/// A personal access token, of which only the hash is kept. The token itself is
+/// `slh_<id>_<secret>` and is shown to its owner once, when created.
+#[derive(Debug, Serialize, Deserialize, Clone, Display)]
+#[display(
+    fmt = "{}",
+    "t!(\"token.display\", id, name, format!(\"{:?}\", scopes), expires_at.format(\"%d.%m.%Y\"))"
+)]
+pub struct ApiToken {
+    pub id: String,
+    pub owner: String,
+    pub name: String,
+    hash: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl ApiToken {
+    /// Create a token, returned along with its secret value
+    fn generate(owner: &str, name: &str, scopes: Vec<Scope>, validity: Duration) -> (Self, String) {
+        let id = random_hex(ID_BYTES);
+        let token = format!("{}{}_{}", TOKEN_PREFIX, id, random_hex(32));
+        let now = Utc::now();
+
+        let api_token = Self {
+            id,
+            owner: owner.to_string(),
+            name: name.to_string(),
+            hash: hash_token(&token),
+            scopes,
+            created_at: now,
+            expires_at: now + validity,
+        };
+        (api_token, token)
+    }
+
+    fn matches(&self, token: &str) -> bool {
+        self.hash == hash_token(token)
+    }
+
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        now >= self.expires_at
+    }
+}
+
+fn random_hex(len: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| format!("{:02x}", rng.gen::<u8>())).collect()
+}
+
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Whether a bearer value looks like a personal access token rather than a session id
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+/// Create a token for `user`, valid for `days` days, and return its secret value
+pub fn create(user: &User, name: &str, scopes: Vec<Scope>, days: i64) -> anyhow::Result<String> {
+    if scopes.is_empty() {
+        bail!(t!("error.token_no_scope"))
+    }
+    if !(1..=MAX_VALIDITY_DAYS).contains(&days) {
+        bail!(t!("error.token_validity", MAX_VALIDITY_DAYS))
+    }
+
+    let (api_token, token) = ApiToken::generate(&user.name, name, scopes, Duration::days(days));
+    DATABASE.lock().unwrap().store_token(&api_token)?;
+    audit::record(
+        &user.name,
+        AuditAction::CreateToken,
+        &format!("{} ({})", api_token.id, api_token.name),
+    );
+
+    Ok(token)
+}
+
+/// Tokens of `user`, expired ones included
+pub fn of(user: &User) -> Vec<ApiToken> {
+    DATABASE.lock().unwrap().get_tokens_of(&user.name)
+}
+
+/// Revoke one of the tokens of `user`, returns whether it existed
+pub fn revoke(user: &User, id: &str) -> bool {
+    let revoked = DATABASE.lock().unwrap().delete_token(&user.name, id);
+    if revoked {
+        audit::record(&user.name, AuditAction::RevokeToken, id);
+    }
+    revoked
+}
+
+/// Get the up-to-date owner of a token and the scopes it grants
+pub fn authenticate(token: &str) -> anyhow::Result<(User, Vec<Scope>)> {
+    let id = token
+        .strip_prefix(TOKEN_PREFIX)
+        .and_then(|rest| rest.split_once('_'))
+        .map(|(id, _)| id)
+        .unwrap_or_default();
+
+    let api_token = match DATABASE.lock().unwrap().get_token(id) {
+        Some(api_token) if api_token.matches(token) => api_token,
+        _ => bail!(t!("error.token_invalid")),
+    };
+    if api_token.is_expired_at(Utc::now()) {
+        bail!(t!("error.token_expired"))
+    }
+
+    match User::get(&api_token.owner) {
+        Some(user) if !user.disabled => Ok((user, api_token.scopes)),
+        _ => bail!(t!("error.token_owner")),
+    }
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Role;
+
+    #[test]
+    fn test_only_the_hash_is_kept() {
+        let (api_token, token) = ApiToken::generate("Toto", "ci", vec![Scope::Read], Duration::days(1));
+
+        assert!(is_api_token(&token));
+        assert!(token.contains(&api_token.id));
+        assert!(!serde_json::to_string(&api_token).unwrap().contains(&token));
+        assert!(api_token.matches(&token));
+        assert!(!api_token.matches(&token.replace(&api_token.id, &"0".repeat(2 * ID_BYTES))));
+        assert_eq!(api_token.id.len(), 2 * ID_BYTES);
+
+        let mut db = DATABASE.lock().unwrap();
+        db.store_token(&api_token).unwrap();
+        assert!(db.store_token(&api_token).is_err());
+    }
+
+    #[test]
+    fn test_token_expires() {
+        let (api_token, _) = ApiToken::generate("Toto", "ci", vec![Scope::Read], Duration::days(1));
+
+        assert!(!api_token.is_expired_at(api_token.created_at + Duration::hours(23)));
+        assert!(api_token.is_expired_at(api_token.created_at + Duration::days(1)));
+    }
+
+    #[test]
+    fn test_authenticate_with_token() {
+        let user = User::new("token admin", "", Role::Admin);
+        DATABASE.lock().unwrap().store_user(&user).unwrap();
+        let token = create(&user, "ci", vec![Scope::Read, Scope::Review], 30).unwrap();
+
+        let (owner, scopes) = authenticate(&token).unwrap();
+        assert_eq!(owner.name, "token admin");
+        assert!(scopes.iter().any(|scope| scope.allows("review")));
+        assert!(!scopes.iter().any(|scope| scope.allows("delete")));
+
+        let id = of(&user).last().unwrap().id.clone();
+        assert!(revoke(&user, &id));
+        assert!(authenticate(&token).is_err());
+
+        assert!(create(&user, "ci", Vec::new(), 30).is_err());
+        assert!(create(&user, "ci", vec![Scope::Read], 0).is_err());
+        assert!(authenticate("slh_nothing").is_err());
+    }
+}
@@ -1,12 +1,26 @@
-use crate::{Review, Role, User};
+use crate::{Criterion, Review, ReviewStatus, Role, Scores, User};
 use anyhow::{anyhow, bail};
 use derive_more::Display;
 use futures::executor::block_on;
-use inquire::{Confirm, CustomType, max_length, Password, PasswordDisplayMode, Select, Text};
+use inquire::error::InquireResult;
+use inquire::validator::Validation;
+use inquire::{Confirm, CustomType, CustomUserError, max_length, MultiSelect, Password, PasswordDisplayMode, Select, Text};
+use chrono::{NaiveDate, Utc};
 use strum::{EnumIter, IntoEnumIterator};
-use crate::utils::authorization::is_authorized;
-use crate::utils::input_validation::{is_name_valid, is_number_in_range, is_password_valid, is_text_length_valid, SHORT_TEXT_MAX_SIZE, REVIEW_MAX_GRADE, REVIEW_MAX_SIZE, REVIEW_MIN_GRADE, REVIEW_MIN_SIZE, PASS_DEFAULT_SCORE};
-use crate::utils::password::{checked_password, hash_password};
+use crate::utils::authorization::{self, is_authorized, AuthzError, Context, Decision};
+use crate::utils::content_filter::{is_comment_acceptable, is_username_acceptable};
+use crate::utils::input_validation::{is_name_valid, normalize_name, is_number_in_range, is_password_valid, is_text_length_valid, SHORT_TEXT_MAX_SIZE, REVIEW_MAX_GRADE, REVIEW_MAX_SIZE, REVIEW_MIN_GRADE, REVIEW_MIN_SIZE, PASS_DEFAULT_SCORE};
+use crate::db::Pagination;
+use crate::service::{self, ServiceError};
+use crate::session::Session;
+use crate::token::{self, Scope};
+use crate::audit::{AuditAction, AuditFilter};
+use crate::index::ReviewKey;
+use crate::search::{ReviewQuery, SortOrder};
+use crate::i18n::{self, t, Lang};
+
+/// Number of reviews shown at once by the listings
+const PAGE_SIZE: usize = 10;
 
 enum ShouldContinue {
     Yes,
@@ -17,6 +31,7 @@ pub fn start() {
     loop_menu(main_menu);
 }
 
+#[allow(clippy::while_let_loop)]
 fn loop_menu<F>(menu_handler: F)
     where
         F: Fn() -> ShouldContinue,
@@ -32,17 +47,17 @@ fn loop_menu<F>(menu_handler: F)
 fn main_menu() -> ShouldContinue {
     #[derive(EnumIter, Display)]
     enum Choice {
-        #[display(fmt = "Se connecter")]
+        #[display(fmt = "{}", "t!(\"menu.login\")")]
         Login,
 
-        #[display(fmt = "S'inscrire")]
+        #[display(fmt = "{}", "t!(\"menu.register\")")]
         Register,
 
-        #[display(fmt = "Quitter")]
+        #[display(fmt = "{}", "t!(\"menu.exit\")")]
         Exit,
     }
 
-    let choice = Select::new("Que voulez-vous faire ?", Choice::iter().collect())
+    let choice = Select::new(&t!("menu.prompt"), Choice::iter().collect())
         .prompt()
         .unwrap();
 
@@ -54,56 +69,55 @@ fn main_menu() -> ShouldContinue {
 }
 
 fn login() -> ShouldContinue {
-    let username = Text::new("Entrez votre nom d'utilisateur : ")
-        .with_validator(is_name_valid)
-        .prompt()
-        .unwrap();
-    let password = Password::new("Entrez votre mot de passe: ")
-        .with_validator(max_length!(SHORT_TEXT_MAX_SIZE, "Le mot de passe doit contenir au plus 64 caractères"))
+    let username = prompt_name(&t!("prompt.username")).unwrap();
+    let password = Password::new(&t!("prompt.password"))
+        .with_validator(max_length!(SHORT_TEXT_MAX_SIZE, t!("validation.password_too_long", SHORT_TEXT_MAX_SIZE)))
         .without_confirmation()
         .prompt()
         .unwrap();
 
-    let user = User::get(&username).unwrap_or_else(|| {
-        //No collision since input validation does not allow empty string as username
-        User::new("", "", Role::Reviewer)
-    });
-    let name = if user.name.is_empty() { None } else { Some(&*user.name) };
-    let result = checked_password(name, &user.password, &password);
-
-    if result {
-        loop_menu(|| user_menu(&user));
-    } else {
-        println!("Le nom d'utilisateur ou le mot de passe est incorrect");
+    match service::authenticate(&username, &password) {
+        Ok(user) => {
+            i18n::apply_preference(user.lang);
+            match Session::open(&user) {
+                Ok((session, _)) => {
+                    loop_menu(|| user_menu(&session));
+                    session.close();
+                }
+                Err(e) => println!("{:#}", e),
+            }
+            i18n::reset();
+        }
+        Err(ServiceError::Authentication) => println!("{}", t!("msg.wrong_credentials")),
+        Err(e) => println!("{}", e),
     }
 
     ShouldContinue::Yes
 }
 
 fn register() -> ShouldContinue {
-    let username = Text::new("Entrez votre nom d'utilisateur : ")
+    let username = Text::new(&t!("prompt.username"))
         .with_validator(is_name_valid)
+        .with_validator(is_username_acceptable)
         .prompt()
+        .map(|name| normalize_name(&name))
         .unwrap();
 
     let cloned_username = username.clone();
-    let password = Password::new("Entrez votre mot de passe : ")
+    let password = Password::new(&t!("prompt.password"))
         .with_display_mode(PasswordDisplayMode::Masked)
-        .with_custom_confirmation_message("Confirmez votre mot de passe : ")
-        .with_custom_confirmation_error_message("Les mots de passe ne correspondent pas")
+        .with_custom_confirmation_message(&t!("prompt.password_confirm"))
+        .with_custom_confirmation_error_message(&t!("msg.passwords_differ"))
         .with_validator(move |input: &str| is_password_valid(&cloned_username, input, PASS_DEFAULT_SCORE))
         .prompt()
         .unwrap();
-    let is_owner = Confirm::new("Êtes-vous propriétaire d'un établissement ?")
+    let is_owner = Confirm::new(&t!("prompt.is_owner"))
         .with_default(false)
         .prompt()
         .unwrap();
 
     let role = if is_owner {
-        let owned_establishment = Text::new("Entrez le nom de votre établissement : ")
-            .with_validator(is_name_valid)
-            .prompt()
-            .unwrap();
+        let owned_establishment = prompt_name(&t!("prompt.own_establishment")).unwrap();
         Role::Owner {
             owned_establishment,
         }
@@ -111,39 +125,113 @@ fn register() -> ShouldContinue {
         Role::Reviewer
     };
 
-    let hashed_password = hash_password(password.as_bytes());
-    let user = User::new(&username, &hashed_password, role);
-    let _ = user.save();
+    if let Err(e) = service::register(&username, &password, role) {
+        println!("{}", e);
+    }
 
     ShouldContinue::Yes
 }
 
+/// Check an authorization, an error being reported and treated as a denial
+fn is_allowed(user: &User, obj: &str, act: &str, ctx: &Context) -> bool {
+    is_granted(block_on(is_authorized(user, obj, act, ctx)))
+}
+
+fn is_granted(decision: Result<Decision, AuthzError>) -> bool {
+    match decision {
+        Ok(decision) => decision == Decision::Allow,
+        Err(e) => {
+            eprintln!("{}", t!("msg.access_denied", e));
+            false
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------------------------
 
-fn user_menu(user: &User) -> ShouldContinue {
+fn user_menu(session: &Session) -> ShouldContinue {
     #[derive(EnumIter, Display)]
     enum Choice {
-        #[display(fmt = "Mes avis")]
+        #[display(fmt = "{}", "t!(\"menu.own_reviews\")")]
         ListOwnReviews,
 
-        #[display(fmt = "Ajouter un avis")]
+        #[display(fmt = "{}", "t!(\"menu.add_review\")")]
         AddReview,
 
-        #[display(fmt = "Avis d'un établissement")]
+        #[display(fmt = "{}", "t!(\"menu.establishment_reviews\")")]
         ListEstablishmentReviews,
 
-        #[display(fmt = "Supprimer un avis")]
+        #[display(fmt = "{}", "t!(\"menu.stats\")")]
+        EstablishmentStats,
+
+        #[display(fmt = "{}", "t!(\"menu.search\")")]
+        SearchReviews,
+
+        #[display(fmt = "{}", "t!(\"menu.vote\")")]
+        VoteReview,
+
+        #[display(fmt = "{}", "t!(\"menu.edit_review\")")]
+        EditReview,
+
+        #[display(fmt = "{}", "t!(\"menu.delete_review\")")]
         DeleteReview,
 
-        #[display(fmt = "Se déconnecter")]
+        #[display(fmt = "{}", "t!(\"menu.toggle_review\")")]
+        ToggleReviewVisibility,
+
+        #[display(fmt = "{}", "t!(\"menu.change_role\")")]
+        ChangeUserRole,
+
+        #[display(fmt = "{}", "t!(\"menu.toggle_account\")")]
+        ToggleAccount,
+
+        #[display(fmt = "{}", "t!(\"menu.toggle_dispute\")")]
+        ToggleDispute,
+
+        #[display(fmt = "{}", "t!(\"menu.set_category\")")]
+        SetCategory,
+
+        #[display(fmt = "{}", "t!(\"menu.sessions\")")]
+        ManageSessions,
+
+        #[display(fmt = "{}", "t!(\"menu.audit\")")]
+        AuditLog,
+
+        #[display(fmt = "{}", "t!(\"menu.policy\")")]
+        ManagePolicy,
+
+        #[display(fmt = "{}", "t!(\"menu.reload_policy\")")]
+        ReloadPolicy,
+
+        #[display(fmt = "{}", "t!(\"menu.explain\")")]
+        ExplainAuthorization,
+
+        #[display(fmt = "{}", "t!(\"menu.tokens\")")]
+        ManageTokens,
+
+        #[display(fmt = "{}", "t!(\"menu.language\")")]
+        ChangeLanguage,
+
+        #[display(fmt = "{}", "t!(\"menu.logout\")")]
         Logout,
     }
 
-    let choice = match Select::new("Que voulez-vous faire ?", Choice::iter().collect()).prompt() {
+    let choice = match Select::new(&t!("menu.prompt"), Choice::iter().collect()).prompt() {
         Ok(choice) => choice,
         Err(..) => return ShouldContinue::Yes,
     };
 
+    // Re-fetch the user on each action so that role changes, account deletion and
+    // session expiry or revocation are taken into account
+    let user = match session.user() {
+        Ok(user) => user,
+        Err(e) => {
+            println!("{}", e);
+            return ShouldContinue::No;
+        }
+    };
+    let user = &user;
+
     match choice {
         Choice::ListOwnReviews => list_own_reviews(user),
         Choice::AddReview => add_review(user).unwrap_or_else(|e| {
@@ -151,80 +239,794 @@ fn user_menu(user: &User) -> ShouldContinue {
             ShouldContinue::Yes
         }),
         Choice::ListEstablishmentReviews => list_establishment_reviews(user),
+        Choice::SearchReviews => search_reviews(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
+        Choice::VoteReview => vote_review(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
+        Choice::EstablishmentStats => show_establishment_stats(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
+        Choice::EditReview => edit_review(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
         Choice::DeleteReview => delete_review(user).unwrap_or_else(|e| {
             println!("{}", e);
             ShouldContinue::Yes
         }),
+        Choice::ToggleReviewVisibility => toggle_review_visibility(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
+        Choice::ChangeUserRole => change_user_role(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
+        Choice::ToggleAccount => toggle_account(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
+        Choice::ToggleDispute => toggle_dispute(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
+        Choice::SetCategory => set_category(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
+        Choice::ManageSessions => manage_sessions(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
+        Choice::AuditLog => show_audit_log(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
+        Choice::ManagePolicy => {
+            if is_allowed(user, "policy", "manage", &Context::new(user)) {
+                loop_menu(|| policy_menu(session));
+            } else {
+                println!("{}", t!("error.not_admin"));
+            }
+            ShouldContinue::Yes
+        }
+        Choice::ReloadPolicy => reload_policy(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
+        Choice::ExplainAuthorization => explain_authorization(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
+        Choice::ManageTokens => {
+            loop_menu(|| token_menu(session));
+            ShouldContinue::Yes
+        }
+        Choice::ChangeLanguage => change_language(user).unwrap_or_else(|e| {
+            println!("{}", e);
+            ShouldContinue::Yes
+        }),
         Choice::Logout => ShouldContinue::No,
     }
 }
 
 fn list_own_reviews(user: &User) -> ShouldContinue {
-    for review in Review::by(&user.name) {
-        println!("{}", review);
-    }
+    browse_reviews(&service::own_reviews(user));
 
     ShouldContinue::Yes
 }
 
 fn add_review(user: &User) -> anyhow::Result<ShouldContinue> {
-    let establishment = Text::new("Entrez le nom de l'établissement : ").prompt()?;
+    let establishment = prompt_establishment()?;
 
-    if !block_on(is_authorized(&user, &establishment, "review")) {
-        bail!("vous n'êtes pas autorisé à ajouter un avis sur cet établissement")
+    if !is_allowed(user, &establishment, "review", &Context::new(user).with_establishment(&establishment)) {
+        bail!(t!("error.cannot_review"))
     }
 
-    let comment = Text::new("Entrez votre commentaire : ")
+    let comment = Text::new(&t!("prompt.comment"))
         .with_validator(|input: &str| is_text_length_valid(input, REVIEW_MIN_SIZE, REVIEW_MAX_SIZE))
+        .with_validator(is_comment_acceptable)
         .prompt()?;
-    let grade = CustomType::new("Entrez votre note : ")
+    let (grade, scores) = prompt_grades(None, &Scores::new())?;
+
+    let review = service::add_review(user, &establishment, &comment, Some(grade), scores)?;
+    if review.status == ReviewStatus::Flagged {
+        println!("{}", t!("msg.review_flagged"));
+    }
+
+    Ok(ShouldContinue::Yes)
+}
+
+/// Ask for optional scores per criterion, then for the overall grade which
+/// defaults to the one derived from the scores
+fn prompt_grades(grade: Option<u8>, scores: &Scores) -> anyhow::Result<(u8, Scores)> {
+    let mut new_scores = Scores::new();
+    let per_criterion = Confirm::new(&t!("prompt.grade_criteria"))
+        .with_default(!scores.is_empty())
+        .prompt()?;
+
+    if per_criterion {
+        for criterion in Criterion::iter() {
+            let current = scores.get(&criterion).map_or(String::new(), u8::to_string);
+            let score = Text::new(&t!("prompt.criterion_grade", criterion))
+                .with_initial_value(&current)
+                .with_validator(|input: &str| match input.trim() {
+                    "" => Ok(Validation::Valid),
+                    input => match input.parse() {
+                        Ok(score) => is_number_in_range(&score, REVIEW_MIN_GRADE, REVIEW_MAX_GRADE),
+                        Err(..) => Ok(Validation::Invalid(t!("error.invalid_value").into())),
+                    },
+                })
+                .prompt()?;
+            if let Ok(score) = score.trim().parse() {
+                new_scores.insert(criterion, score);
+            }
+        }
+    }
+
+    let message = t!("prompt.grade");
+    let mut prompt = CustomType::new(&message)
+        .with_validator(|input: &u8| is_number_in_range(input, REVIEW_MIN_GRADE, REVIEW_MAX_GRADE));
+    if let Some(default) = Review::derived_grade(&new_scores).or(grade) {
+        prompt = prompt.with_default(default);
+    }
+
+    Ok((prompt.prompt()?, new_scores))
+}
+
+fn list_establishment_reviews(user: &User) -> ShouldContinue {
+    let establishment = prompt_establishment().unwrap();
+
+    let mut reviews = service::establishment_reviews(user, &establishment);
+    if reviews.is_empty() {
+        println!("{}", t!("msg.no_review"));
+    }
+
+    let sort = Select::new(&t!("prompt.sort"), SortOrder::iter().collect()).prompt().unwrap_or_default();
+    service::sort_reviews(&mut reviews, sort);
+    browse_reviews(&reviews);
+
+    ShouldContinue::Yes
+}
+
+/// Print reviews a page at a time, letting the user move to the next or previous page
+fn browse_reviews(reviews: &[Review]) {
+    #[derive(EnumIter, Display, PartialEq)]
+    enum Choice {
+        #[display(fmt = "{}", "t!(\"page.next\")")]
+        Next,
+
+        #[display(fmt = "{}", "t!(\"page.previous\")")]
+        Previous,
+
+        #[display(fmt = "{}", "t!(\"menu.back\")")]
+        Back,
+    }
+
+    let tallies = service::tallies();
+    let mut pagination = Pagination::new(0, PAGE_SIZE);
+    loop {
+        let page = pagination.apply(reviews);
+        for review in &page.items {
+            print_review(review);
+            if let Some(tally) = tallies.get(&ReviewKey::of(review)) {
+                println!("  {}", tally);
+            }
+        }
+        if !page.has_next() && !page.has_previous() {
+            return;
+        }
+
+        let choices = Choice::iter()
+            .filter(|choice| match choice {
+                Choice::Next => page.has_next(),
+                Choice::Previous => page.has_previous(),
+                Choice::Back => true,
+            })
+            .collect();
+        let header = t!("page.header", page.offset / PAGE_SIZE + 1, page.total.div_ceil(PAGE_SIZE), page.total);
+        match Select::new(&header, choices).prompt() {
+            Ok(Choice::Next) => pagination.offset += PAGE_SIZE,
+            Ok(Choice::Previous) => pagination.offset -= PAGE_SIZE,
+            Ok(Choice::Back) | Err(..) => return,
+        }
+    }
+}
+
+fn show_establishment_stats(user: &User) -> anyhow::Result<ShouldContinue> {
+    let establishment = prompt_establishment()?;
+
+    let stats = service::establishment_stats(user, &establishment)?;
+    let decimal = |value: Option<f64>| value.map_or("-".to_string(), |value| format!("{:.2}", value));
+    println!(
+        "{}",
+        t!("stats.summary", stats.establishment, stats.count, decimal(stats.mean), decimal(stats.median))
+    );
+    for (grade, count) in (REVIEW_MIN_GRADE..=REVIEW_MAX_GRADE).zip(&stats.distribution) {
+        println!("  {}", t!("stats.grade", grade, "#".repeat(*count), count));
+    }
+
+    if !stats.criteria.is_empty() {
+        println!("{}", t!("stats.criteria"));
+        for criterion in &stats.criteria {
+            println!(
+                "  {}",
+                t!("stats.period", criterion.criterion, format!("{:.2}", criterion.mean), criterion.count)
+            );
+        }
+    }
+
+    if !stats.trend.is_empty() {
+        println!("{}", t!("stats.trend"));
+        for period in &stats.trend {
+            println!("  {}", t!("stats.period", period.month, format!("{:.2}", period.mean), period.count));
+        }
+    }
+
+    Ok(ShouldContinue::Yes)
+}
+
+fn search_reviews(user: &User) -> anyhow::Result<ShouldContinue> {
+    let text = Text::new(&t!("prompt.search_text")).prompt()?;
+    let min_grade = CustomType::new(&t!("prompt.min_grade"))
+        .with_default(REVIEW_MIN_GRADE)
         .with_validator(|input: &u8| is_number_in_range(input, REVIEW_MIN_GRADE, REVIEW_MAX_GRADE))
         .prompt()?;
-    let review = Review::new(&establishment, &user.name, &comment, grade);
+    let max_grade = CustomType::new(&t!("prompt.max_grade"))
+        .with_default(REVIEW_MAX_GRADE)
+        .with_validator(move |input: &u8| is_number_in_range(input, min_grade, REVIEW_MAX_GRADE))
+        .prompt()?;
+    let from = prompt_optional_date(&t!("prompt.from"))?;
+    let to = prompt_optional_date(&t!("prompt.to"))?;
+    let sort = Select::new(&t!("prompt.sort"), SortOrder::iter().collect()).prompt()?;
 
-    review.save()?;
+    let query = ReviewQuery {
+        text,
+        min_grade: Some(min_grade),
+        max_grade: Some(max_grade),
+        from,
+        to,
+        sort,
+    };
+    let reviews = service::search_reviews(user, &query);
+    if reviews.is_empty() {
+        println!("{}", t!("msg.no_review"));
+        return Ok(ShouldContinue::Yes);
+    }
+
+    // Show the details of the chosen reviews until the selection is cancelled
+    let message = t!("prompt.search_results", reviews.len());
+    while let Ok(review) = Select::new(&message, reviews.clone()).prompt() {
+        print_review(&review);
+        println!("  {}", t!("msg.review_date", review.created_at.format("%d.%m.%Y %H:%M")));
+    }
 
     Ok(ShouldContinue::Yes)
 }
 
-fn list_establishment_reviews(user: &User) -> ShouldContinue {
-    let establishment = Text::new("Entrez le nom de l'établissement : ")
+fn vote_review(user: &User) -> anyhow::Result<ShouldContinue> {
+    let establishment = prompt_establishment()?;
+    let name = prompt_name(&t!("prompt.review_author"))?;
+
+    let helpful = Confirm::new(&t!("prompt.helpful")).with_default(true).prompt()?;
+    let tally = service::vote(user, &establishment, &name, helpful)?;
+    println!("{}", t!("msg.vote_recorded", tally));
+
+    Ok(ShouldContinue::Yes)
+}
+
+/// Ask for an establishment name, suggesting the known ones containing what was typed
+fn prompt_establishment() -> InquireResult<String> {
+    Text::new(&t!("prompt.establishment"))
         .with_validator(is_name_valid)
+        .with_autocomplete(suggest_establishments)
         .prompt()
-        .unwrap();
+        .map(|name| normalize_name(&name))
+}
+
+/// Ask for the name of a user or an establishment, composed the way stored names are
+fn prompt_name(message: &str) -> InquireResult<String> {
+    Text::new(message).with_validator(is_name_valid).prompt().map(|name| normalize_name(&name))
+}
+
+fn suggest_establishments(input: &str) -> Result<Vec<String>, CustomUserError> {
+    Ok(service::search_establishments(input))
+}
+
+fn print_review(review: &Review) {
+    match review.status {
+        ReviewStatus::Published => println!("{}", review),
+        ReviewStatus::Hidden => println!("{} {}", review, t!("msg.hidden_marker")),
+        ReviewStatus::Flagged => println!("{} {}", review, t!("msg.flagged_marker")),
+    }
+}
+
+fn edit_review(user: &User) -> anyhow::Result<ShouldContinue> {
+    let establishment = prompt_establishment()?;
+    let review = Review::get(&user.name, &establishment).ok_or_else(|| anyhow!(t!("error.missing_review")))?;
+
+    if !is_allowed(user, &user.name, "edit", &Context::new(user).with_review(&review)) {
+        bail!(t!("error.cannot_edit"))
+    }
+
+    let comment = Text::new(&t!("prompt.comment"))
+        .with_initial_value(&review.comment)
+        .with_validator(|input: &str| is_text_length_valid(input, REVIEW_MIN_SIZE, REVIEW_MAX_SIZE))
+        .with_validator(is_comment_acceptable)
+        .prompt()?;
+    let (grade, scores) = prompt_grades(Some(review.grade), &review.scores)?;
+
+    let edited = service::edit_review(user, &establishment, &comment, Some(grade), scores)?;
+    if edited.status == ReviewStatus::Flagged && review.status != ReviewStatus::Flagged {
+        println!("{}", t!("msg.review_edit_flagged"));
+    }
+
+    Ok(ShouldContinue::Yes)
+}
+
+fn delete_review(user: &User) -> anyhow::Result<ShouldContinue> {
+    let establishment = prompt_establishment()?;
+
+    if !is_allowed(user, &establishment, "delete", &Context::new(user).with_establishment(&establishment)) {
+        bail!(t!("error.cannot_delete"))
+    }
+
+    let name = prompt_name(&t!("prompt.review_author"))?;
+
+    service::delete_review(user, &establishment, &name)?;
+
+    Ok(ShouldContinue::Yes)
+}
+
+fn toggle_review_visibility(user: &User) -> anyhow::Result<ShouldContinue> {
+    let establishment = prompt_establishment()?;
+
+    if !is_allowed(user, &establishment, "hide", &Context::new(user).with_establishment(&establishment)) {
+        bail!(t!("error.cannot_moderate"))
+    }
+
+    let name = prompt_name(&t!("prompt.review_author"))?;
+
+    match service::toggle_review_visibility(user, &establishment, &name)?.status {
+        ReviewStatus::Hidden => println!("{}", t!("msg.review_hidden")),
+        ReviewStatus::Published | ReviewStatus::Flagged => println!("{}", t!("msg.review_visible")),
+    }
+
+    Ok(ShouldContinue::Yes)
+}
+
+fn change_user_role(user: &User) -> anyhow::Result<ShouldContinue> {
+    if !is_allowed(user, "users", "manage", &Context::new(user)) {
+        bail!(t!("error.not_admin"))
+    }
+
+    let name = prompt_name(&t!("prompt.user"))?;
+    if User::get(&name).is_none() {
+        bail!(t!("error.unknown_user"))
+    }
+
+    let target = service::change_role(user, &name, prompt_role()?)?;
+    println!("{}", t!("msg.role_changed", target.name));
+
+    Ok(ShouldContinue::Yes)
+}
+
+fn toggle_account(user: &User) -> anyhow::Result<ShouldContinue> {
+    if !is_allowed(user, "users", "manage", &Context::new(user)) {
+        bail!(t!("error.not_admin"))
+    }
+
+    let name = prompt_name(&t!("prompt.user"))?;
+
+    let target = service::toggle_account(user, &name)?;
+    match target.disabled {
+        true => println!("{}", t!("msg.account_disabled", target.name)),
+        false => println!("{}", t!("msg.account_enabled", target.name)),
+    }
+
+    Ok(ShouldContinue::Yes)
+}
+
+fn toggle_dispute(user: &User) -> anyhow::Result<ShouldContinue> {
+    if !is_allowed(user, "disputes", "manage", &Context::new(user)) {
+        bail!(t!("error.not_admin"))
+    }
+
+    let establishment = prompt_establishment()?;
+
+    if service::toggle_dispute(user, &establishment)? {
+        println!("{}", t!("msg.dispute_opened", establishment));
+    } else {
+        println!("{}", t!("msg.dispute_closed", establishment));
+    }
+
+    Ok(ShouldContinue::Yes)
+}
+
+fn set_category(user: &User) -> anyhow::Result<ShouldContinue> {
+    if !is_allowed(user, "categories", "manage", &Context::new(user)) {
+        bail!(t!("error.not_admin"))
+    }
+
+    let establishment = prompt_establishment()?;
+    let category = Text::new(&t!("prompt.category"))
+        .with_validator(|input: &str| match input.is_empty() {
+            true => Ok(Validation::Valid),
+            false => is_name_valid(input),
+        })
+        .prompt()?;
+
+    match category.is_empty() {
+        true => {
+            service::set_category(user, &establishment, None)?;
+            println!("{}", t!("msg.category_removed", establishment));
+        }
+        false => {
+            service::set_category(user, &establishment, Some(&category))?;
+            println!("{}", t!("msg.category_set", establishment, normalize_name(&category)));
+        }
+    }
 
-    let binding = Review::of(&establishment);
-    let reviews: Vec<&Review> = binding.iter()
-        .filter(|review| block_on(is_authorized(&user, &review.reviewer, "read")) ||
-            block_on(is_authorized(&user, &review.establishment, "read")))
+    Ok(ShouldContinue::Yes)
+}
+
+fn manage_sessions(user: &User) -> anyhow::Result<ShouldContinue> {
+    let sessions = service::sessions(user)?;
+    if sessions.is_empty() {
+        println!("{}", t!("msg.no_session"));
+        return Ok(ShouldContinue::Yes);
+    }
+
+    let session = Select::new(&t!("prompt.session_to_revoke"), sessions).prompt()?;
+    let session = service::revoke_session(user, &session.id)?;
+    println!("{}", t!("msg.session_revoked", session.username));
+
+    Ok(ShouldContinue::Yes)
+}
+
+fn show_audit_log(user: &User) -> anyhow::Result<ShouldContinue> {
+    if !is_allowed(user, "audit", "manage", &Context::new(user)) {
+        bail!(t!("error.not_admin"))
+    }
+
+    let name = Text::new(&t!("prompt.filter_user")).prompt()?;
+    let mut actions: Vec<Option<AuditAction>> = vec![None];
+    actions.extend(AuditAction::iter().map(Some));
+    let labels: Vec<String> = actions
+        .iter()
+        .map(|action| action.map_or(t!("prompt.all_actions"), |action| action.to_string()))
         .collect();
+    let action = Select::new(&t!("prompt.filter_action"), labels).raw_prompt()?.index;
+    let from = prompt_optional_date(&t!("prompt.from"))?;
+    let to = prompt_optional_date(&t!("prompt.to"))?;
 
-    if reviews.is_empty() {
-        println!("Aucun avis trouvé");
+    let filter = AuditFilter {
+        user: Some(name).filter(|name| !name.is_empty()),
+        action: actions[action],
+        from,
+        to,
+    };
+
+    let (entries, altered) = service::audit_entries(user, &filter)?;
+    if let Some(i) = altered {
+        println!("{}", t!("msg.audit_altered", i + 1));
+    }
+    if entries.is_empty() {
+        println!("{}", t!("msg.no_event"));
+    }
+
+    for entry in entries {
+        println!("{}", entry);
+    }
+
+    Ok(ShouldContinue::Yes)
+}
+
+fn reload_policy(user: &User) -> anyhow::Result<ShouldContinue> {
+    if !is_allowed(user, "policy", "manage", &Context::new(user)) {
+        bail!(t!("error.not_admin"))
     }
 
-    for review in reviews {
-        println!("{}", review);
+    block_on(authorization::reload())?;
+    println!("{}", t!("msg.policy_reloaded"));
+
+    Ok(ShouldContinue::Yes)
+}
+
+fn policy_menu(session: &Session) -> ShouldContinue {
+    #[derive(EnumIter, Display)]
+    enum Choice {
+        #[display(fmt = "{}", "t!(\"menu.list_rules\")")]
+        ListRules,
+
+        #[display(fmt = "{}", "t!(\"menu.add_rule\")")]
+        AddRule,
+
+        #[display(fmt = "{}", "t!(\"menu.remove_rule\")")]
+        RemoveRule,
+
+        #[display(fmt = "{}", "t!(\"menu.back\")")]
+        Back,
+    }
+
+    let choice = match Select::new(&t!("menu.prompt"), Choice::iter().collect()).prompt() {
+        Ok(choice) => choice,
+        Err(..) => return ShouldContinue::No,
+    };
+
+    // Same re-check as in the user menu, the session or the admin role may have been revoked meanwhile
+    let user = match session.user() {
+        Ok(user) => user,
+        Err(e) => {
+            println!("{}", e);
+            return ShouldContinue::No;
+        }
+    };
+    if !is_allowed(&user, "policy", "manage", &Context::new(&user)) {
+        println!("{}", t!("error.not_admin"));
+        return ShouldContinue::No;
+    }
+
+    let result = match choice {
+        Choice::ListRules => list_policy_rules(),
+        Choice::AddRule => add_policy_rule(),
+        Choice::RemoveRule => remove_policy_rule(),
+        Choice::Back => return ShouldContinue::No,
+    };
+
+    if let Err(e) = result {
+        println!("{}", e);
     }
 
     ShouldContinue::Yes
 }
 
-fn delete_review(_user: &User) -> anyhow::Result<ShouldContinue> {
-    if !block_on(is_authorized(&_user, "any", "delete")) {
-        bail!("vous n'êtes pas administrateur")
+fn list_policy_rules() -> anyhow::Result<()> {
+    for rule in block_on(authorization::policy_rules())? {
+        println!("p, {}", rule.join(", "));
     }
 
-    let establishment = Text::new("Entrez le nom de l'établissement : ")
-        .with_validator(is_name_valid)
+    Ok(())
+}
+
+fn add_policy_rule() -> anyhow::Result<()> {
+    let mut rule = Vec::new();
+    for field in block_on(authorization::policy_fields())? {
+        rule.push(Text::new(&t!("prompt.rule_field", field)).prompt()?.trim().to_string());
+    }
+
+    block_on(authorization::add_rule(rule))?;
+    println!("{}", t!("msg.rule_added"));
+
+    Ok(())
+}
+
+fn remove_policy_rule() -> anyhow::Result<()> {
+    let mut rules = block_on(authorization::policy_rules())?;
+    if rules.is_empty() {
+        println!("{}", t!("msg.no_rule_to_remove"));
+        return Ok(());
+    }
+
+    let labels = rules.iter().map(|rule| rule.join(", ")).collect();
+    let choice = Select::new(&t!("prompt.rule_to_remove"), labels).raw_prompt()?;
+    let confirmed = Confirm::new(&t!("prompt.confirm_rule_removal", choice.value))
+        .with_default(false)
         .prompt()?;
 
-    let name = Text::new("Entrez le nom de l'auteur de l'avis : ")
+    if confirmed {
+        block_on(authorization::remove_rule(rules.swap_remove(choice.index)))?;
+        println!("{}", t!("msg.rule_removed"));
+    }
+
+    Ok(())
+}
+
+fn token_menu(session: &Session) -> ShouldContinue {
+    #[derive(EnumIter, Display)]
+    enum Choice {
+        #[display(fmt = "{}", "t!(\"menu.list_tokens\")")]
+        ListTokens,
+
+        #[display(fmt = "{}", "t!(\"menu.create_token\")")]
+        CreateToken,
+
+        #[display(fmt = "{}", "t!(\"menu.revoke_token\")")]
+        RevokeToken,
+
+        #[display(fmt = "{}", "t!(\"menu.back\")")]
+        Back,
+    }
+
+    let choice = match Select::new(&t!("menu.prompt"), Choice::iter().collect()).prompt() {
+        Ok(choice) => choice,
+        Err(..) => return ShouldContinue::No,
+    };
+
+    let user = match session.user() {
+        Ok(user) => user,
+        Err(e) => {
+            println!("{}", e);
+            return ShouldContinue::No;
+        }
+    };
+
+    let result = match choice {
+        Choice::ListTokens => list_tokens(&user),
+        Choice::CreateToken => create_token(&user),
+        Choice::RevokeToken => revoke_token(&user),
+        Choice::Back => return ShouldContinue::No,
+    };
+
+    if let Err(e) = result {
+        println!("{}", e);
+    }
+
+    ShouldContinue::Yes
+}
+
+fn list_tokens(user: &User) -> anyhow::Result<()> {
+    let tokens = token::of(user);
+    if tokens.is_empty() {
+        println!("{}", t!("msg.no_token"));
+    }
+
+    let now = Utc::now();
+    for api_token in tokens {
+        match api_token.is_expired_at(now) {
+            true => println!("{} {}", api_token, t!("msg.expired_marker")),
+            false => println!("{}", api_token),
+        }
+    }
+
+    Ok(())
+}
+
+fn create_token(user: &User) -> anyhow::Result<()> {
+    let name = Text::new(&t!("prompt.token_name"))
         .with_validator(is_name_valid)
         .prompt()?;
-    let review = Review::get(&name, &establishment).ok_or(anyhow!("avis manquant"))?;
+    let scopes = MultiSelect::new(&t!("prompt.token_scopes"), Scope::iter().collect()).prompt()?;
+    let days = CustomType::new(&t!("prompt.token_validity"))
+        .with_default(90)
+        .with_validator(|days: &i64| {
+            if (1..=token::MAX_VALIDITY_DAYS).contains(days) {
+                Ok(Validation::Valid)
+            } else {
+                Ok(Validation::Invalid(t!("error.token_validity", token::MAX_VALIDITY_DAYS).into()))
+            }
+        })
+        .prompt()?;
+
+    let secret = token::create(user, &name, scopes, days)?;
+    println!("{}", t!("msg.token_created"));
+    println!("{}", secret);
+
+    Ok(())
+}
+
+fn revoke_token(user: &User) -> anyhow::Result<()> {
+    let tokens = token::of(user);
+    if tokens.is_empty() {
+        println!("{}", t!("msg.no_token_to_revoke"));
+        return Ok(());
+    }
+
+    let api_token = Select::new(&t!("prompt.token_to_revoke"), tokens).prompt()?;
+    if token::revoke(user, &api_token.id) {
+        println!("{}", t!("msg.token_revoked", api_token.name));
+    }
+
+    Ok(())
+}
+
+fn explain_authorization(user: &User) -> anyhow::Result<ShouldContinue> {
+    if !is_allowed(user, "policy", "manage", &Context::new(user)) {
+        bail!(t!("error.not_admin"))
+    }
+
+    let name = prompt_name(&t!("prompt.subject"))?;
+    let subject = match User::get(&name) {
+        Some(subject) => subject,
+        None => {
+            println!("{}", t!("msg.fictional_subject", name));
+            User::new(&name, "", prompt_role()?)
+        }
+    };
+    let obj = Text::new(&t!("prompt.object")).prompt()?;
+    let act = Text::new(&t!("prompt.action")).prompt()?;
+
+    let explanation = block_on(authorization::explain(&subject, &obj, &act, &Context::new(&subject).with_establishment(&obj)))?;
+    match explanation.decision {
+        Decision::Allow => println!("{}", t!("msg.decision_allow")),
+        Decision::Deny => println!("{}", t!("msg.decision_deny")),
+    }
+
+    if explanation.matched.is_empty() {
+        println!("{}", t!("msg.no_matching_rule"));
+    } else {
+        println!("{}", t!("msg.matched_rules"));
+        for rule in explanation.matched {
+            println!("  p, {}", rule.join(", "));
+        }
+    }
 
-    review.delete();
+    if !explanation.unmatched.is_empty() {
+        println!("{}", t!("msg.unmatched_rules", act));
+        for rule in explanation.unmatched {
+            println!("  p, {}", rule.join(", "));
+        }
+    }
 
     Ok(ShouldContinue::Yes)
 }
+
+/// Store the preferred language of the user and switch to it right away
+fn change_language(user: &User) -> anyhow::Result<ShouldContinue> {
+    let lang = Select::new(&t!("prompt.language"), Lang::iter().collect()).prompt()?;
+
+    let mut user = user.clone();
+    user.lang = Some(lang);
+    user.update()?;
+    i18n::apply_preference(user.lang);
+    println!("{}", t!("msg.language_changed"));
+
+    Ok(ShouldContinue::Yes)
+}
+
+/// Ask for a comma-separated list of names, possibly empty
+fn prompt_names(message: &str) -> anyhow::Result<Vec<String>> {
+    let names = Text::new(message)
+        .with_validator(|input: &str| {
+            for name in input.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                if let Validation::Invalid(e) = is_name_valid(name)? {
+                    return Ok(Validation::Invalid(e));
+                }
+            }
+            Ok(Validation::Valid)
+        })
+        .prompt()?;
+
+    Ok(names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(normalize_name)
+        .collect())
+}
+
+fn prompt_role() -> anyhow::Result<Role> {
+    let roles = vec!["Reviewer", "Owner", "Moderator", "Admin"];
+    let role = match Select::new(&t!("prompt.role"), roles).prompt()? {
+        "Owner" => Role::Owner {
+            owned_establishment: prompt_name(&t!("prompt.their_establishment"))?,
+        },
+        "Moderator" => Role::Moderator {
+            establishments: prompt_names(&t!("prompt.moderated"))?,
+            categories: prompt_names(&t!("prompt.moderated_categories"))?,
+        },
+        "Admin" => Role::Admin,
+        _ => Role::Reviewer,
+    };
+
+    Ok(role)
+}
+
+fn prompt_optional_date(message: &str) -> anyhow::Result<Option<NaiveDate>> {
+    let date = Text::new(message)
+        .with_validator(|input: &str| {
+            if input.is_empty() || input.parse::<NaiveDate>().is_ok() {
+                Ok(Validation::Valid)
+            } else {
+                Ok(Validation::Invalid(t!("error.invalid_date").into()))
+            }
+        })
+        .prompt()?;
+
+    Ok(date.parse().ok())
+}
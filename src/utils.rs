@@ -1,3 +1,5 @@
 pub mod input_validation;
+pub mod content_filter;
 pub mod password;
 pub mod authorization;
+pub mod policy_adapter;
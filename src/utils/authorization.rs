@@ -1,20 +1,532 @@
-use casbin::{CoreApi, Enforcer};
-use crate::User;
+use casbin::{Adapter, CoreApi, DefaultModel, Enforcer, FileAdapter, MemoryAdapter, MgmtApi, Model};
+use derive_more::Display;
+use futures::lock::Mutex;
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use casbin::rhai::ImmutableString;
+use serde::ser::{SerializeStruct, Serializer};
+use serde::Serialize;
+use crate::{Review, Role, User};
+use crate::db::DATABASE;
+use crate::i18n::t;
+use crate::audit::{self, AuditAction};
+use crate::utils::input_validation::normalize_name;
+use crate::utils::policy_adapter::{DatabaseAdapter, PolicyRule};
 
-pub async fn is_authorized(sub: &User, obj: &str, act: &str) -> bool {
-    let e = Enforcer::new("authorization/model.conf", "authorization/policy.csv")
-        .await
-        .expect("cannot read model or policy");
+static MODEL_FILE: &str = "authorization/model.conf";
+pub static CASES_FILE: &str = "authorization/cases.csv";
 
-    if let Ok(authorized) = e.enforce((sub, obj, act)) {
-        if authorized {
-            return true;
-        } else {
-            return false;
+// Loaded on first use, then shared by every check until explicitly reloaded
+static ENFORCER: Lazy<Mutex<Option<Enforcer>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum Decision {
+    #[display(fmt = "allow")]
+    Allow,
+
+    #[display(fmt = "deny")]
+    Deny,
+}
+
+#[derive(Debug, Display)]
+pub enum AuthzError {
+    #[display(fmt = "{}", "t!(\"error.authz_load\", _0)")]
+    Load(casbin::Error),
+
+    #[display(fmt = "{}", "t!(\"error.authz_enforce\", _0)")]
+    Enforce(casbin::Error),
+
+    #[display(fmt = "{}", "t!(\"error.authz_rule\", _0)")]
+    InvalidRule(String),
+
+    #[display(fmt = "{}", "t!(\"error.authz_update\", _0)")]
+    Update(casbin::Error),
+}
+
+impl std::error::Error for AuthzError {}
+
+async fn enforcer_with(adapter: impl Adapter + 'static) -> Result<Enforcer, AuthzError> {
+    let model = DefaultModel::from_file(MODEL_FILE).await.map_err(AuthzError::Load)?;
+    new_enforcer(model, adapter).await
+}
+
+/// Enforcer of the policy stored in the database
+async fn load_enforcer() -> Result<Enforcer, AuthzError> {
+    enforcer_with(DatabaseAdapter::default()).await
+}
+
+/// Enforcer of a policy stored in a CSV file
+async fn load_file_enforcer(policy: &str) -> Result<Enforcer, AuthzError> {
+    enforcer_with(FileAdapter::new(policy.to_owned())).await
+}
+
+/// Re-read the model from disk and the policy from the database, the new rules apply to every following check.
+/// If they cannot be loaded, the rules currently in use are kept.
+pub async fn reload() -> Result<(), AuthzError> {
+    let enforcer = load_enforcer().await?;
+    *ENFORCER.lock().await = Some(enforcer);
+    Ok(())
+}
+
+/// User as seen by the model: its role, to be matched through the role hierarchy, and
+/// the establishments this role applies to (`*` standing for all of them)
+#[derive(Serialize, Hash)]
+struct Subject<'a> {
+    name: &'a str,
+    role: &'static str,
+    domains: Vec<String>,
+}
+
+impl<'a> From<&'a User> for Subject<'a> {
+    fn from(user: &'a User) -> Self {
+        let (role, domains) = match &user.role {
+            Role::Reviewer => ("Reviewer", Vec::new()),
+            Role::Owner {
+                owned_establishment,
+            } => ("Owner", vec![owned_establishment.clone()]),
+            Role::Moderator {
+                establishments,
+                categories,
+            } if establishments.is_empty() && categories.is_empty() => ("Moderator", vec!["*".to_string()]),
+            Role::Moderator {
+                establishments,
+                categories,
+            } => {
+                // Categories are expanded on each request, so that moving an establishment
+                // from one category to another applies at once
+                let mut domains = establishments.clone();
+                if !categories.is_empty() {
+                    let db = DATABASE.lock().unwrap();
+                    domains.extend(categories.iter().flat_map(|category| db.get_establishments_in(category)));
+                }
+                ("Moderator", domains)
+            }
+            Role::Admin => ("Admin", Vec::new()),
+        };
+
+        Self {
+            name: &user.name,
+            role,
+            domains,
+        }
+    }
+}
+
+/// Circumstances of a request that policy conditions may depend on, e.g. `r.ctx.review_age < 172800`.
+/// What does not concern the request is `None`, and the lines whose condition depends on it do not apply.
+#[derive(Hash, Clone, Debug)]
+pub struct Context {
+    /// Time of the request, in seconds since the Unix epoch
+    pub now: i64,
+    /// Whether the account making the request is disabled
+    pub disabled: bool,
+    /// Whether the establishment concerned by the request is under dispute
+    pub disputed: Option<bool>,
+    /// Age in seconds of the review concerned by the request
+    pub review_age: Option<i64>,
+    /// Author of the review concerned by the request
+    pub review_author: Option<String>,
+}
+
+impl Context {
+    pub fn new(sub: &User) -> Self {
+        Self {
+            now: Utc::now().timestamp(),
+            disabled: sub.disabled,
+            disputed: None,
+            review_age: None,
+            review_author: None,
+        }
+    }
+
+    /// Add the state of the establishment concerned by the request
+    pub fn with_establishment(mut self, establishment: &str) -> Self {
+        self.disputed = Some(DATABASE.lock().unwrap().is_disputed(establishment));
+        self
+    }
+
+    /// Add the metadata of the review concerned by the request, and the state of its establishment
+    pub fn with_review(mut self, review: &Review) -> Self {
+        self.review_age = Some(self.now - review.created_at.timestamp());
+        self.review_author = Some(review.reviewer.clone());
+        self.with_establishment(&review.establishment)
+    }
+
+    /// Names of the fields that do not concern the request, separated by spaces
+    fn missing(&self) -> String {
+        [
+            ("disputed", self.disputed.is_none()),
+            ("review_age", self.review_age.is_none()),
+            ("review_author", self.review_author.is_none()),
+        ]
+        .iter()
+        .filter(|(_, missing)| *missing)
+        .map(|(field, _)| *field)
+        .collect::<Vec<_>>()
+        .join(" ")
+    }
+}
+
+/// Seen by the model along with `missing`, for the matcher to skip the conditions it cannot evaluate
+impl Serialize for Context {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut ctx = serializer.serialize_struct("Context", 6)?;
+        ctx.serialize_field("now", &self.now)?;
+        ctx.serialize_field("disabled", &self.disabled)?;
+        ctx.serialize_field("disputed", &self.disputed)?;
+        ctx.serialize_field("review_age", &self.review_age)?;
+        ctx.serialize_field("review_author", &self.review_author)?;
+        ctx.serialize_field("missing", &self.missing())?;
+        ctx.end()
+    }
+}
+
+/// Whether a condition only uses fields of the context that are not in `missing`. Registered
+/// as `known` in the model, since evaluating a condition on a missing field may well succeed.
+fn is_known(cond: ImmutableString, missing: ImmutableString) -> bool {
+    missing.split_whitespace().all(|field| {
+        let reference = format!("r.ctx.{}", field);
+        cond.match_indices(&reference).all(|(i, _)| {
+            cond[i + reference.len()..].starts_with(|c: char| c.is_alphanumeric() || c == '_')
+        })
+    })
+}
+
+/// Enforcer of the model with the functions its matcher relies on
+async fn new_enforcer(model: DefaultModel, adapter: impl Adapter + 'static) -> Result<Enforcer, AuthzError> {
+    let mut e = Enforcer::new(model, adapter).await.map_err(AuthzError::Load)?;
+    e.add_function("known", is_known);
+    Ok(e)
+}
+
+fn decide(e: &Enforcer, sub: &User, obj: &str, act: &str, ctx: &Context) -> Result<Decision, AuthzError> {
+    match e.enforce((Subject::from(sub), obj, act, ctx)).map_err(AuthzError::Enforce)? {
+        true => Ok(Decision::Allow),
+        false => Ok(Decision::Deny),
+    }
+}
+
+/// Get the live enforcer out of its slot, loading it first if needed
+async fn loaded(enforcer: &mut Option<Enforcer>) -> Result<&mut Enforcer, AuthzError> {
+    if enforcer.is_none() {
+        *enforcer = Some(load_enforcer().await?);
+    }
+
+    Ok(enforcer.as_mut().unwrap())
+}
+
+async fn with_enforcer<T>(f: impl FnOnce(&Enforcer) -> T) -> Result<T, AuthzError> {
+    let mut enforcer = ENFORCER.lock().await;
+    Ok(f(loaded(&mut enforcer).await?))
+}
+
+async fn enforce(sub: &User, obj: &str, act: &str, ctx: &Context) -> Result<Decision, AuthzError> {
+    with_enforcer(|e| decide(e, sub, obj, act, ctx)).await?
+}
+
+/// Check whether `sub` may perform `act` on `obj`. Denials and errors are audited,
+/// an error must be handled by the caller as a denial.
+pub async fn is_authorized(sub: &User, obj: &str, act: &str, ctx: &Context) -> Result<Decision, AuthzError> {
+    let decision = enforce(sub, obj, act, ctx).await;
+    match &decision {
+        Ok(Decision::Allow) => {}
+        Ok(Decision::Deny) => {
+            audit::record(&sub.name, AuditAction::AccessDenied, &format!("{} {}", act, obj))
+        }
+        Err(e) => audit::record(
+            &sub.name,
+            AuditAction::AuthorizationError,
+            &format!("{} {} : {}", act, obj, e),
+        ),
+    }
+    decision
+}
+
+/// Same as `is_authorized` but denials are not audited. Meant for filtering listings,
+/// where hiding what the user may not access is expected rather than suspicious.
+pub async fn can_access(sub: &User, obj: &str, act: &str, ctx: &Context) -> Result<Decision, AuthzError> {
+    enforce(sub, obj, act, ctx).await
+}
+
+/// Outcome of an authorization request along with the policy lines that led to it
+pub struct Explanation {
+    pub decision: Decision,
+    /// Lines that allowed the request
+    pub matched: Vec<Vec<String>>,
+    /// Lines about the requested action that did not match
+    pub unmatched: Vec<Vec<String>>,
+}
+
+/// Evaluate a request against each line of the live policy separately, to tell
+/// which ones allow it. Nothing is audited, this is meant for diagnosis.
+pub async fn explain(sub: &User, obj: &str, act: &str, ctx: &Context) -> Result<Explanation, AuthzError> {
+    let (decision, rules, grouping_rules) = with_enforcer(|e| {
+        (decide(e, sub, obj, act, ctx), e.get_policy(), e.get_grouping_policy())
+    })
+    .await?;
+
+    let mut explanation = Explanation {
+        decision: decision?,
+        matched: Vec::new(),
+        unmatched: Vec::new(),
+    };
+
+    for rule in rules {
+        let model = DefaultModel::from_file(MODEL_FILE).await.map_err(AuthzError::Load)?;
+        let mut e = new_enforcer(model, MemoryAdapter::default()).await?;
+        if !grouping_rules.is_empty() {
+            e.add_grouping_policies(grouping_rules.clone()).await.map_err(AuthzError::Load)?;
+        }
+        e.add_policy(rule.clone()).await.map_err(AuthzError::Load)?;
+
+        if decide(&e, sub, obj, act, ctx)? == Decision::Allow {
+            explanation.matched.push(rule);
+        } else if rule.iter().any(|field| field == act) {
+            explanation.unmatched.push(rule);
+        }
+    }
+
+    Ok(explanation)
+}
+
+/// Lines of the live policy
+pub async fn policy_rules() -> Result<Vec<Vec<String>>, AuthzError> {
+    with_enforcer(|e| e.get_policy()).await
+}
+
+/// Names of the fields of a policy line, as declared in the model
+pub async fn policy_fields() -> Result<Vec<String>, AuthzError> {
+    let model = DefaultModel::from_file(MODEL_FILE).await.map_err(AuthzError::Load)?;
+    Ok(rule_tokens(&model)
+        .iter()
+        .map(|token| token.trim_start_matches("p_").to_string())
+        .collect())
+}
+
+fn rule_tokens(model: &DefaultModel) -> Vec<String> {
+    model
+        .get_model()
+        .get("p")
+        .and_then(|ast| ast.get("p"))
+        .map(|assertion| assertion.tokens.clone())
+        .unwrap_or_default()
+}
+
+/// Subjects of every role, used to try out new policy lines
+fn sample_subjects() -> Vec<User> {
+    vec![
+        User::new("reviewer", "", Role::Reviewer),
+        User::new(
+            "owner",
+            "",
+            Role::Owner {
+                owned_establishment: "establishment".to_string(),
+            },
+        ),
+        User::new(
+            "moderator",
+            "",
+            Role::Moderator {
+                establishments: vec!["establishment".to_string()],
+                categories: Vec::new(),
+            },
+        ),
+        User::new("admin", "", Role::Admin),
+    ]
+}
+
+/// Check that a policy line fits the model and can be evaluated for every role
+pub async fn validate_rule(rule: &[String]) -> Result<(), AuthzError> {
+    let model = DefaultModel::from_file(MODEL_FILE).await.map_err(AuthzError::Load)?;
+    let fields = rule_tokens(&model).len();
+
+    if rule.len() != fields {
+        return Err(AuthzError::InvalidRule(t!("error.rule_fields", fields)));
+    }
+    if rule.iter().any(|field| field.trim().is_empty() || field.contains([',', '\n'])) {
+        return Err(AuthzError::InvalidRule(t!("error.rule_empty_field")));
+    }
+
+    let mut e = new_enforcer(model, MemoryAdapter::default()).await?;
+    e.add_policy(rule.to_vec()).await.map_err(AuthzError::Update)?;
+
+    // Requesting each field as action makes sure the whole matcher gets evaluated,
+    // with a complete context for the condition to be evaluated too
+    for sub in sample_subjects() {
+        let ctx = Context {
+            disputed: Some(false),
+            review_age: Some(0),
+            review_author: Some(String::new()),
+            ..Context::new(&sub)
+        };
+        for act in rule {
+            decide(&e, &sub, "any", act, &ctx).map_err(|e| AuthzError::InvalidRule(e.to_string()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate a policy line, apply it to the live policy and store it in the database
+pub async fn add_rule(rule: Vec<String>) -> Result<(), AuthzError> {
+    validate_rule(&rule).await?;
+
+    let mut enforcer = ENFORCER.lock().await;
+    let e = loaded(&mut enforcer).await?;
+
+    if !e.add_policy(rule).await.map_err(AuthzError::Update)? {
+        return Err(AuthzError::InvalidRule(t!("error.rule_exists")));
+    }
+    Ok(())
+}
+
+/// Remove a line from the live policy and from the database
+pub async fn remove_rule(rule: Vec<String>) -> Result<(), AuthzError> {
+    let mut enforcer = ENFORCER.lock().await;
+    let e = loaded(&mut enforcer).await?;
+
+    if !e.remove_policy(rule).await.map_err(AuthzError::Update)? {
+        return Err(AuthzError::InvalidRule(t!("error.rule_missing")));
+    }
+    Ok(())
+}
+
+/// Expected outcome of an authorization request, from a regression fixture
+#[derive(Debug)]
+pub struct PolicyCase {
+    pub line: usize,
+    pub user: User,
+    pub obj: String,
+    pub act: String,
+    pub expected: Decision,
+    /// Circumstances of the request, from the optional `key=value` fields of the case
+    pub ctx: Context,
+}
+
+/// A case whose outcome differs from the expected one, or that could not be evaluated
+pub struct CaseFailure {
+    pub case: PolicyCase,
+    pub actual: Result<Decision, AuthzError>,
+}
+
+/// Parse a role written as `Reviewer`, `Owner:<establishment>`, `Moderator[:<establishment>|#<category>;...]` or `Admin`
+pub fn parse_role(role: &str) -> anyhow::Result<Role> {
+    let (name, scope) = role.split_once(':').unwrap_or((role, ""));
+    Ok(match name {
+        "Reviewer" => Role::Reviewer,
+        "Owner" if !scope.is_empty() => Role::Owner {
+            owned_establishment: normalize_name(scope),
+        },
+        "Moderator" => {
+            let (categories, establishments): (Vec<&str>, Vec<&str>) =
+                scope.split(';').filter(|domain| !domain.is_empty()).partition(|domain| domain.starts_with('#'));
+            Role::Moderator {
+                establishments: establishments.into_iter().map(normalize_name).collect(),
+                categories: categories.iter().map(|category| normalize_name(&category[1..])).collect(),
+            }
+        }
+        "Admin" => Role::Admin,
+        _ => anyhow::bail!(t!("error.invalid_role", role)),
+    })
+}
+
+/// Parse regression cases, one `user, role, object, action, allow|deny[, key=value...]` line per case,
+/// the optional fields giving the context of the request: `review_age=<seconds>`, `disputed=true|false`
+/// and `review_author=<user>`
+pub fn parse_cases(csv: &str) -> anyhow::Result<Vec<PolicyCase>> {
+    let mut cases = Vec::new();
+    for (i, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
         }
-    } else {
-        panic!(r"ERROR CASBIN - BETTE CRASH THAN ALLOWING ACCESS ¯\_(ツ)_/¯");
+
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [user, role, obj, act, expected, ref context @ ..] = fields[..] else {
+            anyhow::bail!(t!("error.case_fields", i + 1))
+        };
+        let expected = match expected {
+            "allow" => Decision::Allow,
+            "deny" => Decision::Deny,
+            _ => anyhow::bail!(t!("error.case_decision", i + 1, expected)),
+        };
+        let role = parse_role(role).map_err(|e| anyhow::anyhow!(t!("error.case_line", i + 1, e)))?;
+        let user = User::new(user, "", role);
+
+        let mut ctx = Context::new(&user);
+        for field in context {
+            let parsed = match field.split_once('=') {
+                Some(("review_age", age)) => age.parse().map(|age| ctx.review_age = Some(age)).is_ok(),
+                Some(("disputed", disputed)) => disputed.parse().map(|disputed| ctx.disputed = Some(disputed)).is_ok(),
+                Some(("review_author", author)) => {
+                    ctx.review_author = Some(author.to_string());
+                    true
+                }
+                _ => false,
+            };
+            if !parsed {
+                anyhow::bail!(t!("error.case_context", i + 1, field));
+            }
+        }
+
+        cases.push(PolicyCase {
+            line: i + 1,
+            user,
+            obj: obj.to_string(),
+            act: act.to_string(),
+            expected,
+            ctx,
+        });
+    }
+
+    Ok(cases)
+}
+
+fn failing_cases(e: &Enforcer, cases: Vec<PolicyCase>) -> Vec<CaseFailure> {
+    cases
+        .into_iter()
+        .filter_map(|case| {
+            let actual = decide(e, &case.user, &case.obj, &case.act, &case.ctx);
+            match actual {
+                Ok(decision) if decision == case.expected => None,
+                _ => Some(CaseFailure { case, actual }),
+            }
+        })
+        .collect()
+}
+
+/// Run regression cases against a candidate policy file, returns the failing ones
+pub async fn check_policy(policy: &str, cases: Vec<PolicyCase>) -> Result<Vec<CaseFailure>, AuthzError> {
+    let e = load_file_enforcer(policy).await?;
+    Ok(failing_cases(&e, cases))
+}
+
+/// Run regression cases against the live policy, the one stored in the database
+pub async fn check_live_policy(cases: Vec<PolicyCase>) -> Result<Vec<CaseFailure>, AuthzError> {
+    with_enforcer(|e| failing_cases(e, cases)).await
+}
+
+/// Replace the live policy by the one of a CSV file, provided each of its lines is valid and
+/// every regression case passes. Otherwise the failing cases are returned and nothing changes.
+pub async fn import_policy(policy: &str, cases: Vec<PolicyCase>) -> Result<Vec<CaseFailure>, AuthzError> {
+    let e = load_file_enforcer(policy).await?;
+    for rule in e.get_policy() {
+        validate_rule(&rule).await?;
+    }
+    let failures = failing_cases(&e, cases);
+    if !failures.is_empty() {
+        return Ok(failures);
     }
+
+    let rules = e
+        .get_policy()
+        .into_iter()
+        .map(|rule| PolicyRule::new("p", rule))
+        .chain(e.get_grouping_policy().into_iter().map(|rule| PolicyRule::new("g", rule)))
+        .collect();
+    DATABASE.lock().unwrap().set_policy_rules(rules);
+    reload().await?;
+    Ok(Vec::new())
 }
 
 
@@ -23,8 +535,13 @@ pub async fn is_authorized(sub: &User, obj: &str, act: &str) -> bool {
 #[cfg(test)]
 mod test {
     use futures::executor::block_on;
-    use crate::{Role, User};
-    use crate::utils::authorization::is_authorized;
+    use crate::{Review, Role, User};
+    use crate::utils::authorization::*;
+    use crate::utils::policy_adapter::DEFAULT_POLICY_FILE;
+
+    fn allowed(sub: &User, obj: &str, act: &str) -> bool {
+        block_on(is_authorized(sub, obj, act, &Context::new(sub).with_establishment(obj))).unwrap() == Decision::Allow
+    }
 
     #[test]
     fn test_access_control() {
@@ -40,21 +557,299 @@ mod test {
             },
         );
 
-        assert!(block_on(is_authorized(&admin, "any", "read")));
-        assert!(block_on(is_authorized(&admin, "any", "review")));
-        assert!(block_on(is_authorized(&admin, "any", "delete")));
+        assert!(allowed(&admin, "any", "read"));
+        assert!(allowed(&admin, "any", "review"));
+        assert!(allowed(&admin, "any", "delete"));
+        assert!(allowed(&admin, "sessions", "manage"));
+
+        assert!(!allowed(&reviewer, "any", "delete"));
+        assert!(!allowed(&reviewer, "sessions", "manage"));
+        assert!(allowed(&reviewer, "any", "review"));
+        assert!(allowed(&reviewer, "etab1", "review"));
+        assert!(!allowed(&reviewer, "any", "read"));
+        assert!(allowed(&reviewer, "reviewer", "read"));
+
+        assert!(allowed(&owner, "owner", "read"));
+        assert!(allowed(&owner, "etab1", "read"));
+        assert!(!allowed(&owner, "etab2", "read"));
+        assert!(!allowed(&owner, "etab1", "review"));
+        assert!(allowed(&owner, "etab2", "review"));
+        assert!(!allowed(&owner, "etab2", "delete"));
+        assert!(!allowed(&owner, "sessions", "manage"));
+        assert!(!allowed(&owner, "etab1", "hide"));
+
+        assert!(allowed(&admin, "any", "hide"));
+        assert!(!allowed(&reviewer, "any", "hide"));
+    }
+
+    #[test]
+    fn test_moderator_access_control() {
+        let moderator: User = User::new(
+            "moderator",
+            "73@Lp7xM!RDkS5ot",
+            Role::Moderator {
+                establishments: vec!["etab1".to_string(), "etab2".to_string()],
+                categories: Vec::new(),
+            },
+        );
+        let global_moderator: User = User::new(
+            "global",
+            "73@Lp7xM!RDkS5ot",
+            Role::Moderator {
+                establishments: Vec::new(),
+                categories: Vec::new(),
+            },
+        );
+        let category_moderator: User = User::new(
+            "category",
+            "73@Lp7xM!RDkS5ot",
+            Role::Moderator {
+                establishments: vec!["etab1".to_string()],
+                categories: vec!["Catégorie Modérée".to_string()],
+            },
+        );
+        DATABASE.lock().unwrap().set_category("etab categorized", Some("Catégorie Modérée"));
+        DATABASE.lock().unwrap().set_category("etab elsewhere", Some("Autre Catégorie"));
+
+        for act in ["read", "hide", "delete"] {
+            assert!(allowed(&moderator, "etab1", act));
+            assert!(allowed(&moderator, "etab2", act));
+            assert!(!allowed(&moderator, "etab3", act));
+            assert!(allowed(&global_moderator, "etab3", act));
+            assert!(allowed(&category_moderator, "etab1", act));
+            assert!(allowed(&category_moderator, "etab categorized", act));
+            assert!(!allowed(&category_moderator, "etab elsewhere", act));
+            assert!(!allowed(&category_moderator, "etab2", act));
+        }
+
+        for moderator in [&moderator, &global_moderator, &category_moderator] {
+            assert!(allowed(moderator, "etab1", "review"));
+            assert!(!allowed(moderator, "users", "manage"));
+            assert!(!allowed(moderator, "policy", "manage"));
+            assert!(!allowed(moderator, "sessions", "manage"));
+        }
+    }
+
+    /// Expected outcome of a request, written independently from the policy, about a review
+    /// of the user when `own_review` and young enough to be edited when `fresh`
+    fn expected(sub: &User, obj: &str, act: &str, own_review: bool, fresh: bool) -> bool {
+        let is_self = obj == sub.name;
+        match (&sub.role, act) {
+            (Role::Admin, "read" | "review" | "hide" | "delete" | "manage" | "stats") => true,
+            (_, "edit") => is_self && fresh,
+            (Role::Owner { owned_establishment }, "vote") => obj != owned_establishment && !own_review,
+            (_, "vote") => !own_review,
+            (Role::Reviewer, "review") => true,
+            (Role::Reviewer, "read") => is_self,
+            (Role::Owner { owned_establishment }, "read") => is_self || obj == owned_establishment,
+            (Role::Owner { owned_establishment }, "stats") => obj == owned_establishment,
+            (Role::Owner { owned_establishment }, "review") => obj != owned_establishment,
+            (Role::Moderator { .. }, "review") => true,
+            (Role::Moderator { .. }, "read") if is_self => true,
+            (Role::Moderator { establishments, .. }, "read" | "hide" | "delete") => {
+                establishments.is_empty() || establishments.iter().any(|e| e == obj)
+            }
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_access_control_combinations() {
+        let subjects = [
+            User::new("reviewer", "", Role::Reviewer),
+            User::new(
+                "owner",
+                "",
+                Role::Owner {
+                    owned_establishment: "etab1".to_string(),
+                },
+            ),
+            User::new(
+                "moderator",
+                "",
+                Role::Moderator {
+                    establishments: vec!["etab1".to_string()],
+                    categories: Vec::new(),
+                },
+            ),
+            User::new(
+                "global",
+                "",
+                Role::Moderator {
+                    establishments: Vec::new(),
+                    categories: Vec::new(),
+                },
+            ),
+            User::new("admin", "", Role::Admin),
+        ];
+        let objects = ["etab1", "etab2", "reviewer", "owner", "moderator", "global", "admin", "users", "policy"];
+        let actions = ["read", "review", "hide", "delete", "manage", "stats", "vote", "edit", "unknown"];
+
+        for sub in &subjects {
+            for obj in objects {
+                for act in actions {
+                    for (own_review, fresh) in [(false, true), (true, false)] {
+                        let ctx = Context {
+                            review_age: Some(if fresh { 0 } else { 172800 }),
+                            review_author: Some(if own_review { sub.name.clone() } else { "other".to_string() }),
+                            ..Context::new(sub).with_establishment(obj)
+                        };
+                        let decision = block_on(can_access(sub, obj, act, &ctx)).unwrap();
+                        assert_eq!(
+                            decision == Decision::Allow,
+                            expected(sub, obj, act, own_review, fresh),
+                            "{:?} {} {} own_review={} fresh={}",
+                            sub.role,
+                            obj,
+                            act,
+                            own_review,
+                            fresh
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reload_keeps_rules() {
+        let reviewer: User = User::new("reviewer", "73@Lp7xM!RDkS5ot", Role::Reviewer);
+
+        assert_eq!(block_on(can_access(&reviewer, "reviewer", "read", &Context::new(&reviewer))).unwrap(), Decision::Allow);
+        block_on(reload()).unwrap();
+        assert_eq!(block_on(can_access(&reviewer, "reviewer", "read", &Context::new(&reviewer))).unwrap(), Decision::Allow);
+        assert_eq!(block_on(can_access(&reviewer, "other", "read", &Context::new(&reviewer))).unwrap(), Decision::Deny);
+    }
+
+    #[test]
+    fn test_invalid_policy_is_an_error() {
+        let reviewer: User = User::new("reviewer", "73@Lp7xM!RDkS5ot", Role::Reviewer);
+        let policy = std::env::temp_dir().join("slh-malformed-policy.csv");
+        std::fs::write(&policy, "p, Reviewer, *, read, r.ctx.now >\n").unwrap();
+
+        assert!(block_on(load_file_enforcer("authorization/missing.csv")).is_err());
+
+        let e = block_on(load_file_enforcer(policy.to_str().unwrap())).unwrap();
+        assert!(matches!(decide(&e, &reviewer, "any", "read", &Context::new(&reviewer)), Err(AuthzError::Enforce(..))));
+    }
+
+    #[test]
+    fn test_explain_decision() {
+        let reviewer: User = User::new("reviewer", "73@Lp7xM!RDkS5ot", Role::Reviewer);
+
+        let explanation = block_on(explain(&reviewer, "reviewer", "read", &Context::new(&reviewer))).unwrap();
+        assert_eq!(explanation.decision, Decision::Allow);
+        assert_eq!(explanation.matched, vec![vec!["Reviewer", "@self", "read", "true"]]);
+
+        let explanation = block_on(explain(&reviewer, "any", "delete", &Context::new(&reviewer))).unwrap();
+        assert_eq!(explanation.decision, Decision::Deny);
+        assert!(explanation.matched.is_empty());
+        assert!(!explanation.unmatched.is_empty());
+    }
+
+    #[test]
+    fn test_validate_rule() {
+        let rule = |fields: &[&str]| fields.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+
+        assert!(block_on(validate_rule(&rule(&["Moderator", "*", "vote", "true"]))).is_ok());
+        assert!(block_on(validate_rule(&rule(&["Owner", "McDonalds", "read", "!r.ctx.disputed"]))).is_ok());
+
+        assert!(block_on(validate_rule(&rule(&["Reviewer", "*", "read"]))).is_err());
+        assert!(block_on(validate_rule(&rule(&["Reviewer", "*", "read", "true", "extra"]))).is_err());
+        assert!(block_on(validate_rule(&rule(&["Reviewer", "", "read", "true"]))).is_err());
+        assert!(block_on(validate_rule(&rule(&["Reviewer", "a, b", "read", "true"]))).is_err());
+        assert!(block_on(validate_rule(&rule(&["Reviewer", "*", "read", "r.ctx.now >"]))).is_err());
+    }
+
+    #[test]
+    fn test_context_conditions() {
+        let reviewer = User::new("reviewer", "73@Lp7xM!RDkS5ot", Role::Reviewer);
+        let owner = User::new(
+            "owner",
+            "73@Lp7xM!RDkS5ot",
+            Role::Owner {
+                owned_establishment: "etab1".to_string(),
+            },
+        );
+        let mut admin = User::new("admin", "73@Lp7xM!RDkS5ot", Role::Admin);
+        let decide = |sub: &User, obj: &str, act: &str, ctx: &Context| {
+            block_on(can_access(sub, obj, act, ctx)).unwrap() == Decision::Allow
+        };
+
+        let mut review = Review::new("etab1", "reviewer", "Très bon accueil", 4);
+        assert!(decide(&reviewer, "reviewer", "edit", &Context::new(&reviewer).with_review(&review)));
+        review.created_at -= chrono::Duration::hours(49);
+        assert!(!decide(&reviewer, "reviewer", "edit", &Context::new(&reviewer).with_review(&review)));
+        assert!(!decide(&reviewer, "other", "edit", &Context::new(&reviewer)));
+
+        // Conditions on what the request does not say deny it
+        assert!(!decide(&reviewer, "reviewer", "edit", &Context::new(&reviewer)));
+        assert!(!decide(&owner, "etab1", "read", &Context::new(&owner)));
+        assert!(!decide(&reviewer, "etab1", "vote", &Context::new(&reviewer)));
+
+        let mut ctx = Context::new(&owner);
+        ctx.disputed = Some(false);
+        assert!(decide(&owner, "etab1", "read", &ctx));
+        ctx.disputed = Some(true);
+        assert!(!decide(&owner, "etab1", "read", &ctx));
+        assert!(decide(&admin, "etab1", "read", &ctx));
+
+        admin.disabled = true;
+        for act in ["read", "review", "hide", "delete", "manage"] {
+            assert!(!decide(&admin, "etab1", act, &Context::new(&admin)));
+        }
+    }
+
+    #[test]
+    fn test_policy_regression_cases() {
+        let cases = parse_cases(&std::fs::read_to_string(CASES_FILE).unwrap()).unwrap();
+        assert!(!cases.is_empty());
+
+        let failures = block_on(check_policy(DEFAULT_POLICY_FILE, cases)).unwrap();
+        for failure in &failures {
+            println!("{:?} -> {:?}", failure.case, failure.actual);
+        }
+        assert!(failures.is_empty());
+    }
 
-        assert!(!block_on(is_authorized(&reviewer, "any", "delete")));
-        assert!(block_on(is_authorized(&reviewer, "any", "review")));
-        assert!(block_on(is_authorized(&reviewer, "etab1", "review")));
-        assert!(!block_on(is_authorized(&reviewer, "any", "read")));
-        assert!(block_on(is_authorized(&reviewer, "reviewer", "read")));
+    #[test]
+    fn test_import_policy_refuses_regressions() {
+        let cases = || parse_cases(&std::fs::read_to_string(CASES_FILE).unwrap()).unwrap();
+        let reviewer: User = User::new("reviewer", "73@Lp7xM!RDkS5ot", Role::Reviewer);
+        let policy = std::env::temp_dir().join("slh-regressing-policy.csv");
+        std::fs::write(&policy, "p, Reviewer, *, read, true\n").unwrap();
+
+        assert!(!block_on(import_policy(policy.to_str().unwrap(), cases())).unwrap().is_empty());
+        assert_eq!(block_on(can_access(&reviewer, "other", "read", &Context::new(&reviewer))).unwrap(), Decision::Deny);
+        assert!(block_on(check_live_policy(cases())).unwrap().is_empty());
 
-        assert!(block_on(is_authorized(&owner, "owner", "read")));
-        assert!(block_on(is_authorized(&owner, "etab1", "read")));
-        assert!(!block_on(is_authorized(&owner, "etab2", "read")));
-        assert!(!block_on(is_authorized(&owner, "etab1", "review")));
-        assert!(block_on(is_authorized(&owner, "etab2", "review")));
-        assert!(!block_on(is_authorized(&owner, "etab2", "delete")));
+        assert!(block_on(import_policy(DEFAULT_POLICY_FILE, cases())).unwrap().is_empty());
+        assert!(block_on(check_live_policy(cases())).unwrap().is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_cases() {
+        let cases = parse_cases("# comment\n\nbob, Owner:etab1, etab1, read, allow\n").unwrap();
+        assert_eq!(cases.len(), 1);
+        assert_eq!(cases[0].line, 3);
+        assert_eq!(cases[0].expected, Decision::Allow);
+
+        assert!(parse_cases("bob, Owner, etab1, read, allow").is_err());
+        assert!(parse_cases("bob, Reviewer, etab1, read").is_err());
+        assert!(parse_cases("bob, Reviewer, etab1, read, maybe").is_err());
+        assert!(parse_cases("bob, Superuser, etab1, read, allow").is_err());
+
+        let cases = parse_cases("bob, Owner:etab1, etab1, read, deny, disputed=true, review_age=60, review_author=al").unwrap();
+        assert_eq!(cases[0].ctx.disputed, Some(true));
+        assert_eq!(cases[0].ctx.review_age, Some(60));
+        assert_eq!(cases[0].ctx.review_author.as_deref(), Some("al"));
+        assert!(parse_cases("bob, Owner:etab1, etab1, read, deny, disputed=maybe").is_err());
+        assert!(parse_cases("bob, Owner:etab1, etab1, read, deny, unknown=1").is_err());
+
+        let Role::Moderator { establishments, categories } = parse_role("Moderator:etab1;#Fast Food").unwrap() else {
+            panic!("not a moderator")
+        };
+        assert_eq!(establishments, vec!["etab1".to_string()]);
+        assert_eq!(categories, vec!["Fast Food".to_string()]);
+    }
+}
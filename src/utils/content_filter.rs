@@ -0,0 +1,214 @@
+use crate::i18n::t;
+use crate::search::fold;
+use inquire::validator::Validation::{self, Invalid, Valid};
+use inquire::CustomUserError;
+use once_cell::sync::Lazy;
+use std::{env, fs};
+
+pub static DEFAULT_WORDS_FILE: &str = "moderation/words.txt";
+static WORDS_FILE_ENV: &str = "SLH_WORDS_FILE";
+
+// Without its list the filter cannot tell what is offensive, so it then refuses every text
+static FILTER: Lazy<Result<ContentFilter, String>> = Lazy::new(|| {
+    let path = env::var(WORDS_FILE_ENV).unwrap_or_else(|_| DEFAULT_WORDS_FILE.to_string());
+    let filter = load(&path);
+    if let Err(e) = &filter {
+        eprintln!("{}", e);
+    }
+    filter
+});
+
+fn load(path: &str) -> Result<ContentFilter, String> {
+    fs::read_to_string(path)
+        .map(|list| ContentFilter::parse(&list))
+        .map_err(|e| t!("error.words_file", path, e))
+}
+
+/// Endings with which a listed word still matches, for plurals and feminine forms
+const SUFFIXES: [&str; 5] = ["", "s", "x", "e", "es"];
+
+/// Offensive words, masked in comments or causing them to be rejected
+#[derive(Debug, Default)]
+pub struct ContentFilter {
+    masked: Vec<String>,
+    rejected: Vec<String>,
+}
+
+impl ContentFilter {
+    /// Parse a list with one word per line, `!` marking the words to reject, `#` starting a comment
+    pub fn parse(list: &str) -> Self {
+        let mut filter = Self::default();
+        for line in list.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            match line.strip_prefix('!') {
+                Some(word) => filter.rejected.push(normalize(word)),
+                None => filter.masked.push(normalize(line)),
+            }
+        }
+        filter
+    }
+
+    /// Whether a token is one of `words`, possibly followed by digits as in "merde42"
+    fn matches(words: &[String], token: &str) -> bool {
+        [token, token.trim_end_matches(|c: char| c.is_ascii_digit())].iter().any(|token| {
+            let token = normalize(token);
+            words
+                .iter()
+                .any(|word| SUFFIXES.iter().any(|suffix| stretches(&token, &format!("{}{}", word, suffix))))
+        })
+    }
+
+    /// The text with its offensive words masked, or `None` if one of them calls for rejecting it
+    pub fn apply(&self, text: &str) -> Option<String> {
+        let mut filtered = String::with_capacity(text.len());
+        let mut last = 0;
+        for (start, token) in tokens(text) {
+            if Self::matches(&self.rejected, token) {
+                return None;
+            }
+            if Self::matches(&self.masked, token) {
+                filtered.push_str(&text[last..start]);
+                let mut chars = token.chars();
+                filtered.extend(chars.next());
+                filtered.extend(chars.map(|_| '*'));
+                last = start + token.len();
+            }
+        }
+        filtered.push_str(&text[last..]);
+        Some(filtered)
+    }
+
+    /// Whether the text contains no listed word at all
+    pub fn is_clean(&self, text: &str) -> bool {
+        tokens(text).all(|(_, token)| !Self::matches(&self.rejected, token) && !Self::matches(&self.masked, token))
+    }
+}
+
+/// Letter that a character stands for in leetspeak
+fn unleet(c: char) -> char {
+    match c {
+        '0' => 'o',
+        '1' | '!' | '|' => 'i',
+        '3' | '€' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' => 't',
+        '8' => 'b',
+        c => c,
+    }
+}
+
+/// Fold accents and case and decode leetspeak, so that "MÉRDE" and "m3rd3" both give "merde"
+fn normalize(word: &str) -> String {
+    fold(word).chars().map(unleet).collect()
+}
+
+/// Letters of a word with how many times each is repeated in a row
+fn runs(word: &str) -> Vec<(char, usize)> {
+    let mut runs: Vec<(char, usize)> = Vec::new();
+    for c in word.chars() {
+        match runs.last_mut() {
+            Some((last, count)) if *last == c => *count += 1,
+            _ => runs.push((c, 1)),
+        }
+    }
+    runs
+}
+
+/// Whether `token` is `word` with some of its letters repeated, as "merdeee" for "merde".
+/// Letters are never dropped, so that "pis" is not taken for "piss".
+fn stretches(token: &str, word: &str) -> bool {
+    let (token, word) = (runs(token), runs(word));
+    token.len() == word.len()
+        && token.iter().zip(&word).all(|((c, n), (w, m))| c == w && n >= m)
+}
+
+/// Words of a text with their byte offset, leetspeak symbols included ("@ss", "sh!t")
+/// except for trailing exclamation marks
+fn tokens(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let is_word = |c: char| c.is_alphanumeric() || unleet(c) != c;
+    text.split(move |c: char| !is_word(c))
+        .map(move |token| (token.as_ptr() as usize - text.as_ptr() as usize, token.trim_end_matches('!')))
+        .filter(|(_, token)| !token.is_empty())
+}
+
+/// Mask the offensive words of a comment, or refuse it if it contains one of the gravest
+/// There are no owner replies to reviews yet, they are to go through this filter once added.
+pub fn filter_comment(comment: &str) -> Result<String, String> {
+    let filter = FILTER.as_ref().map_err(Clone::clone)?;
+    filter.apply(comment).ok_or_else(|| t!("validation.offensive_text"))
+}
+
+pub fn is_comment_acceptable(comment: &str) -> Result<Validation, CustomUserError> {
+    match filter_comment(comment) {
+        Ok(..) => Ok(Valid),
+        Err(message) => Ok(Invalid(message.into())),
+    }
+}
+
+/// Usernames cannot be masked, so any listed word makes them invalid
+pub fn is_username_acceptable(name: &str) -> Result<Validation, CustomUserError> {
+    match FILTER.as_ref().map(|filter| filter.is_clean(name)) {
+        Ok(true) => Ok(Valid),
+        Ok(false) => Ok(Invalid(t!("validation.offensive_name").into())),
+        Err(e) => Ok(Invalid(e.clone().into())),
+    }
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter() -> ContentFilter {
+        ContentFilter::parse("# test\nmerde\nshit\n\n!connard\n")
+    }
+
+    #[test]
+    fn test_masks_and_rejects() {
+        let filter = filter();
+
+        assert_eq!(filter.apply("Service lent, merde !").unwrap(), "Service lent, m**** !");
+        assert_eq!(filter.apply("M3RDÉÉÉ et sh!t").unwrap(), "M****** et s***");
+        assert_eq!(filter.apply("Des merdes").unwrap(), "Des m*****");
+        assert_eq!(filter.apply("Emmerdant mais bon").unwrap(), "Emmerdant mais bon");
+        assert!(filter.apply("Serveur c0nnard").is_none());
+        assert!(filter.apply("Quelles CONNARDES").is_none());
+    }
+
+    #[test]
+    fn test_usernames() {
+        let filter = filter();
+
+        assert!(filter.is_clean("Toto"));
+        assert!(filter.is_clean("Shitake Lover"));
+        assert!(!filter.is_clean("Sh1t Lover"));
+        assert!(!filter.is_clean("merde42"));
+    }
+
+    #[test]
+    fn test_repeated_letters_are_not_collapsed() {
+        let filter = ContentFilter::parse("piss\nmerde\n");
+
+        assert_eq!(filter.apply("Tant pis pour le dessert").unwrap(), "Tant pis pour le dessert");
+        assert!(filter.is_clean("Tant Pis"));
+        assert_eq!(filter.apply("Quelle pisssse").unwrap(), "Quelle p******");
+        assert_eq!(filter.apply("Mmmerde").unwrap(), "M******");
+        assert!(filter.is_clean("Merd"));
+    }
+
+    #[test]
+    fn test_missing_list_is_an_error() {
+        assert!(load("moderation/missing.txt").is_err());
+        assert!(load(DEFAULT_WORDS_FILE).is_ok());
+    }
+
+    #[test]
+    fn test_default_list_is_valid() {
+        let filter = ContentFilter::parse(&fs::read_to_string(DEFAULT_WORDS_FILE).unwrap());
+
+        assert!(!filter.masked.is_empty());
+        assert!(!filter.rejected.is_empty());
+        assert_eq!(filter.apply("Très bon café").unwrap(), "Très bon café");
+    }
+}
@@ -3,6 +3,9 @@ use inquire::validator::{StringValidator, Validation};
 use inquire::validator::Validation::{Invalid, Valid};
 use zxcvbn::zxcvbn;
 use regex::Regex;
+use crate::i18n::t;
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::skeleton;
 
 pub const PASS_MIN_SIZE: usize = 8;
 pub const PASS_DEFAULT_SCORE: u8 = 2;
@@ -12,9 +15,22 @@ pub const REVIEW_MAX_SIZE: usize = 650;
 pub const REVIEW_MIN_GRADE: u8 = 1;
 pub const REVIEW_MAX_GRADE: u8 = 5;
 
+/// Compose a name (NFC), so that "Café" typed with a combining accent is the same name
+pub fn normalize_name(name: &str) -> String {
+    name.nfc().collect()
+}
+
+/// Form of a name shared by the names it can be mistaken for according to the Unicode
+/// confusables, ignoring case, e.g. "T0T0", "TOTO" and "toto"
+pub fn name_skeleton(name: &str) -> String {
+    skeleton(&name.to_lowercase()).flat_map(char::to_lowercase).collect()
+}
+
 pub fn is_name_valid(name: &str) -> Result<Validation, CustomUserError> {
+    let name = &normalize_name(name);
+
     //Check length
-    let length_valid = max_length!(SHORT_TEXT_MAX_SIZE, format!("Le nom doit contenir au plus {} caractères", SHORT_TEXT_MAX_SIZE))
+    let length_valid = max_length!(SHORT_TEXT_MAX_SIZE, t!("validation.name_too_long", SHORT_TEXT_MAX_SIZE))
         .validate(name)?;
 
     if length_valid == Valid {
@@ -22,7 +38,7 @@ pub fn is_name_valid(name: &str) -> Result<Validation, CustomUserError> {
         let regex_str = r"^[a-zA-Z0-9À-ÖØ-öø-ÿ]+(?:\s[a-zA-Z0-9À-ÖØ-öø-ÿ]+)*$";
         let regex = Regex::new(regex_str).unwrap();
         if !regex.is_match(name) {
-            return Ok(Invalid("Le nom entré est invalide".into()));
+            return Ok(Invalid(t!("validation.name_invalid").into()));
         }
     }
 
@@ -31,15 +47,15 @@ pub fn is_name_valid(name: &str) -> Result<Validation, CustomUserError> {
 
 pub fn is_text_length_valid(text: &str, lower_bound: usize, upper_bound: usize) -> Result<Validation, CustomUserError> {
     if lower_bound >= upper_bound {
-        return Ok(Invalid("Mauvaise utilisation: La borne inf. doit être plus petite que la borne sup.".into()));
+        return Ok(Invalid(t!("validation.bounds").into()));
     }
 
-    let min_valid = min_length!(lower_bound, format!("Texte trop court (min {} caractères)", lower_bound))
+    let min_valid = min_length!(lower_bound, t!("validation.text_too_short", lower_bound))
         .validate(text)?;
     if min_valid != Valid {
         return Ok(min_valid);
     }
-    let max_valid = max_length!(upper_bound, format!("Texte trop long (max {} caractères)", upper_bound))
+    let max_valid = max_length!(upper_bound, t!("validation.text_too_long", upper_bound))
         .validate(text)?;
     if max_valid != Valid {
         return Ok(max_valid);
@@ -50,26 +66,26 @@ pub fn is_text_length_valid(text: &str, lower_bound: usize, upper_bound: usize)
 
 pub fn is_number_in_range(input: &u8, lower_bound: u8, upper_bound: u8) -> Result<Validation, CustomUserError> {
     if lower_bound >= upper_bound {
-        return Ok(Invalid("Mauvaise utilisation: La borne inf. doit être plus petite que la borne sup.".into()));
+        return Ok(Invalid(t!("validation.bounds").into()));
     }
     if input > &upper_bound {
-        return Ok(Invalid("Le chiffre est trop grand".into()));
+        return Ok(Invalid(t!("validation.number_too_big").into()));
     }
     if input < &lower_bound {
-        return Ok(Invalid("Le chiffre est trop petit".into()));
+        return Ok(Invalid(t!("validation.number_too_small").into()));
     }
     Ok(Valid)
 }
 
 pub fn is_password_valid(username: &str, password: &str, score_lower_bound: u8) -> Result<Validation, CustomUserError> {
     //Check length
-    let max_valid = max_length!(SHORT_TEXT_MAX_SIZE, format!("Le mot de passe doit contenir au plus {} caractères", SHORT_TEXT_MAX_SIZE))
-        .validate(&password)?;
+    let max_valid = max_length!(SHORT_TEXT_MAX_SIZE, t!("validation.password_too_long", SHORT_TEXT_MAX_SIZE))
+        .validate(password)?;
     if max_valid != Valid {
         return Ok(max_valid);
     }
-    let min_valid = min_length!(PASS_MIN_SIZE, format!("Le mot de passe doit contenir au moins {} caractères", PASS_MIN_SIZE))
-        .validate(&password)?;
+    let min_valid = min_length!(PASS_MIN_SIZE, t!("validation.password_too_short", PASS_MIN_SIZE))
+        .validate(password)?;
     if min_valid != Valid {
         return Ok(min_valid);
     }
@@ -78,7 +94,7 @@ pub fn is_password_valid(username: &str, password: &str, score_lower_bound: u8)
     let inputs = [username];
     let estimate = zxcvbn(password, &inputs).unwrap().score();
     if estimate <= score_lower_bound {
-        return Ok(Invalid("Le mot de passe n'est pas assez fort".into()));
+        return Ok(Invalid(t!("validation.password_weak").into()));
     }
     Ok(Valid)
 }
@@ -88,7 +104,7 @@ pub fn is_password_valid(username: &str, password: &str, score_lower_bound: u8)
 #[cfg(test)]
 mod tests {
     use inquire::validator::Validation::{Invalid, Valid};
-    use crate::utils::input_validation::{is_name_valid, is_number_in_range, is_password_valid, is_text_length_valid};
+    use crate::utils::input_validation::{is_name_valid, is_number_in_range, is_password_valid, is_text_length_valid, name_skeleton, normalize_name};
 
 
     #[test]
@@ -116,6 +132,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_borrow)]
     fn is_short_text_length_valid_returns_err_if_length_invalid() {
         //Given
         let lb = 8;
@@ -252,4 +269,18 @@ mod tests {
         assert_eq!(result5, expected);
         assert_eq!(result6, expected_long);
     }
-}
\ No newline at end of file
+    #[test]
+    fn names_are_normalized_and_confusables_detected() {
+        //Given
+        let composed = "Caf\u{e9}";
+        let decomposed = "Cafe\u{301}"; //e followed by a combining acute accent
+        //When
+        let result = is_name_valid(decomposed);
+        //Then
+        assert_eq!(result.unwrap(), Valid);
+        assert_eq!(normalize_name(decomposed), composed);
+        assert_eq!(name_skeleton("T0T0"), name_skeleton("Toto"));
+        assert_eq!(name_skeleton("rnax"), name_skeleton("max"));
+        assert_ne!(name_skeleton("Toto"), name_skeleton("Tata"));
+    }
+}
@@ -0,0 +1,291 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use casbin::{Adapter, Filter, Model};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use crate::db::DATABASE;
+use crate::i18n::t;
+
+pub static DEFAULT_POLICY_FILE: &str = "authorization/policy.csv";
+
+/// Format of the stored policy, to be increased whenever the model changes in an incompatible way:
+/// - 0: attribute-based rules `p, <sub_rule>, <act>`
+/// - 1: role hierarchy and domains `p, <role>, <domain>, <act>` and `g, <role>, <inherited role>`
+/// - 2: request context and conditions `p, <role>, <domain>, <act>, <cond>`
+/// - 3: scope keywords written `@self`, `@domain` and `@foreign`, which no name can be mistaken for
+pub const POLICY_VERSION: u32 = 3;
+
+/// Scope keywords of the domain field, as written before version 3
+const LEGACY_SCOPES: [&str; 3] = ["self", "domain", "foreign"];
+
+/// Policy shipped with version 0, whose rules the default policy replaces as a whole
+const LEGACY_DEFAULT_POLICY: &str = r#"
+p, r.sub.role.name == "Admin", delete
+p, r.sub.role.name == "Admin" || r.sub.role.name == "Reviewer", review
+p, r.sub.role.name == "Admin", read
+p, r.sub.role.name == "Admin", hide
+p, r.sub.role.name == "Admin", manage
+p, r.sub.role.name == "Reviewer" && r.sub.name == r.obj, read
+p, r.sub.role.name == "Owner" && r.sub.role.owned_establishment != r.obj, review
+p, r.sub.role.name == "Owner" && r.sub.role.owned_establishment == r.obj, read
+p, r.sub.role.name == "Owner" && r.sub.name == r.obj, read
+p, r.sub.role.name == "Moderator" && (r.sub.role.establishments.is_empty() || r.obj in r.sub.role.establishments), read
+p, r.sub.role.name == "Moderator" && (r.sub.role.establishments.is_empty() || r.obj in r.sub.role.establishments), hide
+p, r.sub.role.name == "Moderator" && (r.sub.role.establishments.is_empty() || r.obj in r.sub.role.establishments), delete
+"#;
+
+/// A policy line as stored in the database, e.g. `p, Reviewer, @self, read, true`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PolicyRule {
+    pub ptype: String,
+    pub rule: Vec<String>,
+}
+
+impl PolicyRule {
+    pub fn new(ptype: &str, rule: Vec<String>) -> Self {
+        Self {
+            ptype: ptype.to_string(),
+            rule,
+        }
+    }
+
+    /// Section of the model the rule belongs to, "p" or "g", empty for an invalid type
+    fn sec(&self) -> &str {
+        self.ptype.get(..1).unwrap_or_default()
+    }
+
+    /// Whether the fields starting at `field_index` equal `field_values`, empty values matching anything
+    fn matches(&self, field_index: usize, field_values: &[String]) -> bool {
+        field_values.iter().enumerate().all(|(i, value)| {
+            value.is_empty() || self.rule.get(field_index + i) == Some(value)
+        })
+    }
+}
+
+/// Parse a policy in the casbin CSV format, one `ptype, field, ...` line per rule
+pub fn parse_policy(csv: &str) -> anyhow::Result<Vec<PolicyRule>> {
+    csv.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.split(',').map(|field| field.trim().to_string());
+            match fields.next() {
+                Some(ptype) if !ptype.is_empty() => Ok(PolicyRule::new(&ptype, fields.collect())),
+                _ => anyhow::bail!(t!("error.rule_type", line)),
+            }
+        })
+        .collect()
+}
+
+/// Policy shipped with the application, used to initialize the database
+pub fn default_policy() -> anyhow::Result<Vec<PolicyRule>> {
+    let csv = fs::read_to_string(DEFAULT_POLICY_FILE)
+        .with_context(|| t!("error.read_file", DEFAULT_POLICY_FILE))?;
+    parse_policy(&csv)
+}
+
+/// Rewrite rules stored in an older format for the current model. Rules of version 0 are
+/// attribute conditions that do not translate into roles: the default policy takes the place
+/// of the shipped ones and the others are returned as not converted, to be reported.
+pub fn migrate(version: u32, rules: Vec<PolicyRule>) -> anyhow::Result<(Vec<PolicyRule>, Vec<PolicyRule>)> {
+    if version == 0 {
+        let legacy = parse_policy(LEGACY_DEFAULT_POLICY)?;
+        let unconverted = rules.into_iter().filter(|rule| !legacy.contains(rule)).collect();
+        return Ok((default_policy()?, unconverted));
+    }
+
+    let migrated = rules
+        .into_iter()
+        .map(|mut rule| {
+            if rule.sec() == "p" {
+                // Version 1 rules had no condition
+                if version < 2 {
+                    rule.rule.push("true".to_string());
+                }
+                if let Some(scope) = rule.rule.get_mut(1).filter(|dom| LEGACY_SCOPES.contains(&dom.as_str())) {
+                    *scope = format!("@{}", scope);
+                }
+            }
+            rule
+        })
+        .collect();
+    Ok((migrated, Vec::new()))
+}
+
+/// Casbin adapter keeping the policy in the application database, alongside users and reviews
+#[derive(Default)]
+pub struct DatabaseAdapter {
+    is_filtered: bool,
+}
+
+impl DatabaseAdapter {
+    fn load(&self, m: &mut dyn Model, rules: Vec<PolicyRule>) {
+        for policy in rules {
+            let sec = policy.sec().to_string();
+            m.add_policy(&sec, &policy.ptype, policy.rule);
+        }
+    }
+}
+
+#[async_trait]
+impl Adapter for DatabaseAdapter {
+    async fn load_policy(&mut self, m: &mut dyn Model) -> casbin::Result<()> {
+        self.is_filtered = false;
+        let rules = DATABASE.lock().unwrap().get_policy_rules();
+        self.load(m, rules);
+        Ok(())
+    }
+
+    async fn load_filtered_policy<'a>(&mut self, m: &mut dyn Model, f: Filter<'a>) -> casbin::Result<()> {
+        let to_strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        let (p, g) = (to_strings(&f.p), to_strings(&f.g));
+
+        let rules: Vec<PolicyRule> = DATABASE
+            .lock()
+            .unwrap()
+            .get_policy_rules()
+            .into_iter()
+            .filter(|policy| match policy.sec() {
+                "p" => policy.matches(0, &p),
+                "g" => policy.matches(0, &g),
+                _ => true,
+            })
+            .collect();
+
+        self.is_filtered = true;
+        self.load(m, rules);
+        Ok(())
+    }
+
+    async fn save_policy(&mut self, m: &mut dyn Model) -> casbin::Result<()> {
+        let mut rules = Vec::new();
+        for sec in ["p", "g"] {
+            if let Some(ast_map) = m.get_model().get(sec) {
+                for (ptype, ast) in ast_map {
+                    rules.extend(
+                        ast.get_policy()
+                            .iter()
+                            .map(|rule| PolicyRule::new(ptype, rule.clone())),
+                    );
+                }
+            }
+        }
+
+        DATABASE.lock().unwrap().set_policy_rules(rules);
+        Ok(())
+    }
+
+    async fn clear_policy(&mut self) -> casbin::Result<()> {
+        DATABASE.lock().unwrap().set_policy_rules(Vec::new());
+        Ok(())
+    }
+
+    fn is_filtered(&self) -> bool {
+        self.is_filtered
+    }
+
+    async fn add_policy(&mut self, _sec: &str, ptype: &str, rule: Vec<String>) -> casbin::Result<bool> {
+        Ok(DATABASE
+            .lock()
+            .unwrap()
+            .store_policy_rule(&PolicyRule::new(ptype, rule)))
+    }
+
+    async fn add_policies(&mut self, _sec: &str, ptype: &str, rules: Vec<Vec<String>>) -> casbin::Result<bool> {
+        let mut db = DATABASE.lock().unwrap();
+        let mut added = false;
+        for rule in rules {
+            added |= db.store_policy_rule(&PolicyRule::new(ptype, rule));
+        }
+        Ok(added)
+    }
+
+    async fn remove_policy(&mut self, _sec: &str, ptype: &str, rule: Vec<String>) -> casbin::Result<bool> {
+        let policy = PolicyRule::new(ptype, rule);
+        Ok(DATABASE
+            .lock()
+            .unwrap()
+            .delete_policy_rules(|stored| *stored == policy))
+    }
+
+    async fn remove_policies(&mut self, _sec: &str, ptype: &str, rules: Vec<Vec<String>>) -> casbin::Result<bool> {
+        let policies: Vec<PolicyRule> = rules
+            .into_iter()
+            .map(|rule| PolicyRule::new(ptype, rule))
+            .collect();
+        Ok(DATABASE
+            .lock()
+            .unwrap()
+            .delete_policy_rules(|stored| policies.contains(stored)))
+    }
+
+    async fn remove_filtered_policy(
+        &mut self,
+        _sec: &str,
+        ptype: &str,
+        field_index: usize,
+        field_values: Vec<String>,
+    ) -> casbin::Result<bool> {
+        Ok(DATABASE
+            .lock()
+            .unwrap()
+            .delete_policy_rules(|stored| stored.ptype == ptype && stored.matches(field_index, &field_values)))
+    }
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policy() {
+        let csv = "p, r.sub.name == r.obj, read\n\n# comment\ng, Admin, Reviewer\n";
+        let rules = parse_policy(csv).unwrap();
+
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0], PolicyRule::new("p", vec!["r.sub.name == r.obj".to_string(), "read".to_string()]));
+        assert_eq!(rules[1].sec(), "g");
+        assert!(parse_policy(", Admin, *, read, true").is_err());
+        assert_eq!(PolicyRule::new("é", Vec::new()).sec(), "");
+    }
+
+    #[test]
+    fn test_default_policy_is_loaded() {
+        let rules = default_policy().unwrap();
+        assert!(!rules.is_empty());
+        assert!(rules.iter().all(|rule| match rule.sec() {
+            "p" => rule.rule.len() == 4,
+            "g" => rule.rule.len() == 2,
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn test_migrate() {
+        let (rules, unconverted) = migrate(1, parse_policy("p, Owner, domain, read\np, Reviewer, etab1, read\ng, Admin, Moderator").unwrap()).unwrap();
+        assert_eq!(rules, parse_policy("p, Owner, @domain, read, true\np, Reviewer, etab1, read, true\ng, Admin, Moderator").unwrap());
+        assert!(unconverted.is_empty());
+
+        let (rules, _) = migrate(2, parse_policy("p, Reviewer, self, edit, r.ctx.review_age < 60").unwrap()).unwrap();
+        assert_eq!(rules, parse_policy("p, Reviewer, @self, edit, r.ctx.review_age < 60").unwrap());
+
+        let custom = "p, r.sub.name == \"bob\", manage";
+        let legacy = format!("{}\n{}", LEGACY_DEFAULT_POLICY, custom);
+        let (rules, unconverted) = migrate(0, parse_policy(&legacy).unwrap()).unwrap();
+        assert_eq!(rules, default_policy().unwrap());
+        assert_eq!(unconverted, parse_policy(custom).unwrap());
+    }
+
+    #[test]
+    fn test_rule_matches_fields() {
+        let rule = PolicyRule::new("p", vec!["a".to_string(), "b".to_string()]);
+
+        assert!(rule.matches(0, &["a".to_string()]));
+        assert!(rule.matches(1, &["b".to_string()]));
+        assert!(rule.matches(0, &["".to_string(), "b".to_string()]));
+        assert!(!rule.matches(0, &["b".to_string()]));
+        assert!(!rule.matches(1, &["b".to_string(), "c".to_string()]));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::index::ReviewKey;
+use crate::i18n::t;
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Opinion of a user on whether a review was helpful, a user having at most one per review
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Vote {
+    pub voter: String,
+    pub review: ReviewKey,
+    pub helpful: bool,
+}
+
+/// Votes received by a review
+#[derive(Debug, Serialize, Clone, Copy, Default, PartialEq, Eq, Display)]
+#[display(fmt = "{}", "t!(\"vote.tally\", helpful, unhelpful)")]
+pub struct Tally {
+    pub helpful: usize,
+    pub unhelpful: usize,
+}
+
+impl Tally {
+    /// Used to rank reviews, the most helpful first
+    pub fn score(&self) -> i64 {
+        self.helpful as i64 - self.unhelpful as i64
+    }
+}
+
+pub fn tally(votes: &[Vote]) -> HashMap<ReviewKey, Tally> {
+    let mut tallies: HashMap<ReviewKey, Tally> = HashMap::new();
+    for vote in votes {
+        let tally = tallies.entry(vote.review.clone()).or_default();
+        match vote.helpful {
+            true => tally.helpful += 1,
+            false => tally.unhelpful += 1,
+        }
+    }
+    tallies
+}
+
+// ------------------ UNIT TESTS --------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tally() {
+        let review = ReviewKey {
+            reviewer: "a".to_string(),
+            establishment: "Vote Bistrot".to_string(),
+        };
+        let vote = |voter: &str, helpful| Vote {
+            voter: voter.to_string(),
+            review: review.clone(),
+            helpful,
+        };
+
+        let tallies = tally(&[vote("b", true), vote("c", true), vote("d", false)]);
+        assert_eq!(tallies[&review], Tally { helpful: 2, unhelpful: 1 });
+        assert_eq!(tallies[&review].score(), 1);
+    }
+}